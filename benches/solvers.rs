@@ -0,0 +1,147 @@
+//! Benchmarks of the solvers on reproducible tasks, run with `cargo bench`.
+//!
+//! Every task is solved at several resolutions with the same seed. Measured are the sweeps per
+//! second of `sweep`, the time to reach the accepted delta with `sweep` (successive over
+//! relaxation) and with `evaluate_multi_thread` for several thread counts. The results are
+//! written as json lines to `bench_results.jsonl` (or the path in `BENCH_OUTPUT`).
+
+extern crate numeric_laplace;
+
+use numeric_laplace::*;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const SEED: [u8; 32] = [4; 32];
+const ACCEPTED_DELTA: f64 = 1e-3;
+const OVER_RELAXATION: f64 = 1.9;
+const RESOLUTIONS: [usize; 4] = [1, 2, 4, 8];
+const THREADS: [usize; 3] = [2, 4, 8];
+/// sweeps are repeated until at least this much time has passed
+const MIN_SWEEP_TIME: Duration = Duration::from_millis(500);
+
+/// a named task, the same as in `main`
+struct Task {
+    name: &'static str,
+    width: usize,
+    height: usize,
+    fixed_boxes: Vec<FixedBox>,
+    origin: (f64, f64),
+}
+
+fn tasks() -> Vec<Task> {
+    vec![
+        Task {
+            name: "electrodes",
+            width: 50,
+            height: 50,
+            fixed_boxes: vec![
+                FixedBox { x: -5, y: 5, width: 10, height: 10, potential: 100.0 },
+                FixedBox { x: 30, y: 20, width: 5, height: 18, potential: 0.0 },
+                FixedBox { x: 30, y: -2, width: 5, height: 18, potential: 0.0 },
+            ],
+            origin: (-10.0, 25.0),
+        },
+        Task {
+            name: "capacitor",
+            width: 80,
+            height: 40,
+            fixed_boxes: vec![
+                FixedBox { x: 20, y: 15, width: 40, height: 2, potential: 100.0 },
+                FixedBox { x: 20, y: 25, width: 40, height: 2, potential: -100.0 },
+            ],
+            origin: (0.0, 40.0),
+        },
+    ]
+}
+
+struct Measurement {
+    task: &'static str,
+    nodes_per_unit: usize,
+    nodes: usize,
+    solver: String,
+    threads: usize,
+    sweeps_per_second: Option<f64>,
+    iterations: Option<usize>,
+    seconds: f64,
+}
+
+impl Measurement {
+    fn json(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("null"));
+        format!("{{\"task\":\"{}\",\"nodes_per_unit\":{},\"nodes\":{},\"solver\":\"{}\",\
+                 \"threads\":{},\"sweeps_per_second\":{},\"iterations\":{},\"seconds\":{}}}",
+                self.task, self.nodes_per_unit, self.nodes, self.solver, self.threads,
+                optional(self.sweeps_per_second.map(|s| s.to_string())),
+                optional(self.iterations.map(|i| i.to_string())), self.seconds)
+    }
+}
+
+fn main() {
+    let path = env::var("BENCH_OUTPUT").unwrap_or_else(|_| String::from("bench_results.jsonl"));
+    let mut measurements = Vec::new();
+
+    for task in tasks().iter() {
+        for &nodes_per_unit in RESOLUTIONS.iter() {
+            let builder = GridBuilder::new()
+                .size(task.width, task.height)
+                .resolution(nodes_per_unit)
+                .origin(task.origin.0, task.origin.1)
+                .invert(false, true)
+                .electrodes(&task.fixed_boxes)
+                .seed(SEED);
+            let new_grid = || builder.build().expect("Invalid task!");
+            let nodes = new_grid().nodes.len();
+            let measurement = |solver: &str, threads, sweeps_per_second, iterations, seconds| {
+                Measurement { task: task.name, nodes_per_unit, nodes, solver: solver.to_string(),
+                    threads, sweeps_per_second, iterations, seconds }
+            };
+
+            // raw sweep speed
+            let mut grid = new_grid();
+            let mut sweeps = 0;
+            let start = Instant::now();
+            while start.elapsed() < MIN_SWEEP_TIME {
+                grid.sweep(OVER_RELAXATION);
+                sweeps += 1;
+            }
+            let seconds = start.elapsed().as_secs_f64();
+            measurements.push(measurement("sweep", 1, Some(sweeps as f64 / seconds), Some(sweeps),
+                                          seconds));
+
+            // time to tolerance, single threaded
+            let mut grid = new_grid();
+            let mut iterations = 1;
+            let start = Instant::now();
+            while grid.sweep(OVER_RELAXATION) > ACCEPTED_DELTA {
+                iterations += 1;
+            }
+            let seconds = start.elapsed().as_secs_f64();
+            measurements.push(measurement("sor", 1, Some(iterations as f64 / seconds),
+                                          Some(iterations), seconds));
+
+            // time to tolerance, multi threaded
+            for &threads in THREADS.iter() {
+                let mut grid = new_grid();
+                let start = Instant::now();
+                assert!(grid.evaluate_multi_thread(ACCEPTED_DELTA, OVER_RELAXATION, threads),
+                        "the solve on {} threads did not converge", threads);
+                measurements.push(measurement("multi_thread", threads, None, None,
+                                              start.elapsed().as_secs_f64()));
+            }
+        }
+    }
+
+    println!();
+    let mut file = File::create(&path).expect("Could not create benchmark output file!");
+    for measurement in measurements.iter() {
+        println!("{:>10} {:>2} nodes/unit {:>7} nodes {:>12} {:>2} threads: {:>9.4} s{}",
+                 measurement.task, measurement.nodes_per_unit, measurement.nodes,
+                 measurement.solver, measurement.threads, measurement.seconds,
+                 measurement.sweeps_per_second.map(|s| format!(", {:.1} sweeps/s", s))
+                     .unwrap_or_default());
+        writeln!(file, "{}", measurement.json()).expect("Could not write benchmark output!");
+    }
+    println!("results written to {}", path);
+}
@@ -0,0 +1,486 @@
+//! # Adaptive Refinement
+//!
+//! a quadtree of cells on top of the cells between the nodes of a coarse grid. After every solve
+//! the error of every cell is estimated from the jump of the gradient across it (large next to
+//! the corners of electrodes and in narrow gaps, tiny in empty space), the cells with the
+//! biggest estimates are split in four and the mesh is solved again. Neighbouring cells differ by
+//! at most one level.
+//!
+//! The potential lives at the cell centers. The flux through a face is the difference of the
+//! potentials divided by the distance of the centers normal to the face, times the length of the
+//! face. Across faces between cells of different levels the tangential offset of the centers is
+//! ignored, which is only first order accurate there. Electrodes are cells whose center lies
+//! inside a fixed box (the true box, not the nodes of the coarse grid), their potential applies
+//! on their faces, so refinement also resolves the edges of the boxes better. The border is a
+//! Dirichlet boundary on the faces of the outermost cells, at the position of the border nodes
+//! of the coarse grid.
+
+use std::collections::HashMap;
+
+use super::{iterate, Geometry, Grid, GridError, Scale};
+use builder::{BoundaryCondition, GridBuilder};
+use sample::{Interpolation, SampleError};
+
+/// How `Grid::solve_adaptive` refines
+///
+/// # Fields
+///
+/// * `target` - refinement stops once the error estimate of every cell is below this (a
+///   potential)
+/// * `max_level` - cells are split at most this many times, a cell of level `l` is `2^-l` node
+///   spacings of the coarse grid wide
+/// * `fraction` - every pass splits the cells with an estimate above `target` and above
+///   `fraction` times the biggest estimate
+/// * `max_passes` - the most solves (including the first one) before giving up on `target`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refinement {
+    pub target: f64,
+    pub max_level: u32,
+    pub fraction: f64,
+    pub max_passes: usize,
+}
+
+impl Refinement {
+    /// up to 6 levels and 20 passes, splitting the cells above half of the biggest estimate
+    pub fn new(target: f64) -> Refinement {
+        Refinement { target, max_level: 6, fraction: 0.5, max_passes: 20 }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Cell {
+    level: u32,
+    column: usize,
+    row: usize,
+    value: f64,
+    fixed: bool,
+    children: Option<[usize; 4]>,
+}
+
+/// left, right, top and bottom in internal coordinates
+const DIRECTIONS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// half open range of internal coordinates
+type Range = (f64, f64);
+
+/// what is on the other side of one face of a cell
+enum Neighbours {
+    /// the border with the given potential at the center of the face
+    Boundary(f64),
+    /// one cell of the same or the next coarser level or two of the next finer one
+    Cells(Vec<usize>),
+}
+
+/// the linear system of the dynamic cells, the new value of a cell is
+/// `(sum of weight * neighbour + constant) / diagonal`
+struct System {
+    dynamic: Vec<usize>,
+    /// links of dynamic cell `k` are `links[start[k]..start[k + 1]]`
+    start: Vec<usize>,
+    links: Vec<(usize, f64)>,
+    constant: Vec<f64>,
+    diagonal: Vec<f64>,
+}
+
+/// A quadtree mesh solved with `Grid::solve_adaptive`, it can be sampled directly or resampled
+/// to a regular `Grid` with `to_grid` for all the writers
+pub struct AdaptiveGrid {
+    cells: Vec<Cell>,
+    /// cell index by level, column and row
+    index: HashMap<(u32, usize, usize), usize>,
+    /// cells of level 0 in x and y direction, one less than the nodes of the coarse grid
+    columns: usize,
+    rows: usize,
+    /// scale of the coarse grid, internal coordinates of the cells are in its node spacings
+    scale: Scale,
+    boundary: BoundaryCondition,
+    /// columns, rows and potential of every fixed box
+    electrodes: Vec<(Range, Range, f64)>,
+}
+
+impl Grid {
+    /// Solves the task described by `task` (its initial guess is used for the coarse grid) with
+    /// adaptive refinement: the grid of `task` is solved first and its cells form the coarsest
+    /// level of the quadtree, then the mesh is refined according to `refinement` and solved again
+    /// (until the max delta is below `accepted_delta`) until every error estimate is below the
+    /// target, no cell can be split any more or the passes are used up. Fails for the same
+    /// reasons as `GridBuilder::build`, with `Unsupported` for graded axes, an axisymmetric
+    /// geometry, conductors, an open border, conduction or a source and with `NotConverged` once
+    /// a solve does not converge
+    pub fn solve_adaptive(task: &GridBuilder, refinement: &Refinement, accepted_delta: f64,
+                          over_relaxation: f64) -> Result<AdaptiveGrid, GridError> {
+        let mut coarse = task.build()?;
+        if coarse.scale.x.is_graded() || coarse.scale.y.is_graded() {
+            return Err(GridError::Unsupported(String::from(
+                "adaptive refinement needs evenly spaced axes")));
+        }
+        if coarse.geometry() != Geometry::Planar || !coarse.conductors().is_empty() ||
+            coarse.open_boundary().is_some() || coarse.conduction().is_some() ||
+            task.source.is_some() {
+            return Err(GridError::Unsupported(String::from(
+                "adaptive refinement only supports planar grids with fixed boxes, a fixed border \
+                 and no source")));
+        }
+        if coarse.width < 2 || coarse.height() < 2 {
+            return Err(GridError::ZeroSize { width: task.width, height: task.height });
+        }
+        if !iterate(accepted_delta, None, |_| Some(coarse.sweep(over_relaxation))).1 {
+            return Err(GridError::NotConverged {
+                spacing: (coarse.scale.x.spacing, coarse.scale.y.spacing) });
+        }
+        let mut mesh = AdaptiveGrid::from_coarse(&coarse, task);
+
+        for pass in 1..refinement.max_passes.max(1) + 1 {
+            let (iterations, converged) = mesh.solve(accepted_delta, over_relaxation);
+            if !converged {
+                return Err(GridError::NotConverged { spacing: mesh.finest_spacing() });
+            }
+            let max_error = mesh.max_error_estimate();
+            println!("pass {}: {} cells, {} iterations, max error estimate = {}", pass,
+                     mesh.leaf_count(), iterations, max_error);
+            if max_error <= refinement.target || pass == refinement.max_passes ||
+                mesh.refine(refinement) == 0 {
+                break;
+            }
+        }
+        Ok(mesh)
+    }
+}
+
+impl AdaptiveGrid {
+    /// the cells between the nodes of `coarse` (the grid of `task`) with the potential
+    /// interpolated at their centers
+    fn from_coarse(coarse: &Grid, task: &GridBuilder) -> AdaptiveGrid {
+        let scale = coarse.scale.clone();
+        let electrodes = task.electrodes.iter().map(|fixed_box| {
+            let (x, y) = (fixed_box.x as f64, fixed_box.y as f64);
+            let x_end = if scale.x.invert { x - fixed_box.width as f64 }
+                else { x + fixed_box.width as f64 };
+            let y_end = if scale.y.invert { y - fixed_box.height as f64 }
+                else { y + fixed_box.height as f64 };
+            let (c_0, c_1) = (scale.x.to_internal(x), scale.x.to_internal(x_end));
+            let (r_0, r_1) = (scale.y.to_internal(y), scale.y.to_internal(y_end));
+            ((c_0.min(c_1), c_0.max(c_1)), (r_0.min(r_1), r_0.max(r_1)), fixed_box.potential)
+        }).collect();
+        let mut mesh = AdaptiveGrid {
+            cells: Vec::new(),
+            index: HashMap::new(),
+            columns: coarse.width - 1,
+            rows: coarse.height() - 1,
+            scale,
+            boundary: task.boundary,
+            electrodes,
+        };
+        for row in 0..mesh.rows {
+            for column in 0..mesh.columns {
+                let (c, r) = (column as f64 + 0.5, row as f64 + 0.5);
+                let value = coarse.sample_internal(c, r, Interpolation::Bilinear);
+                mesh.add_cell(0, column, row, value);
+            }
+        }
+        mesh
+    }
+
+    /// number of cells that are not split
+    pub fn leaf_count(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.children.is_none()).count()
+    }
+
+    /// the level of the smallest cells
+    pub fn max_level(&self) -> u32 {
+        self.cells.iter().map(|cell| cell.level).max().unwrap_or(0)
+    }
+
+    /// the biggest error estimate of all cells
+    pub fn max_error_estimate(&self) -> f64 {
+        self.error_estimates().iter().fold(0.0, |max, &(_, error)| max.max(error))
+    }
+
+    /// Returns the potential at the external coordinates `x` and `y`, reconstructed linearly
+    /// from the cell containing them and its neighbours. Points outside of the grid result in
+    /// `SampleError::OutsideDomain`
+    pub fn sample(&self, x: f64, y: f64) -> Result<f64, SampleError> {
+        const EDGE_TOLERANCE: f64 = 1e-9;
+        let (column, row) = self.scale.to_internal(x, y);
+        let (max_column, max_row) = (self.columns as f64, self.rows as f64);
+        if !(column >= -EDGE_TOLERANCE && column <= max_column + EDGE_TOLERANCE &&
+            row >= -EDGE_TOLERANCE && row <= max_row + EDGE_TOLERANCE) {
+            return Err(SampleError::OutsideDomain { x, y });
+        }
+        Ok(self.sample_internal(column.max(0.0).min(max_column), row.max(0.0).min(max_row)))
+    }
+
+    /// Resamples the mesh to a regular grid with the node spacing of the coarse grid divided by
+    /// `2^level`, e.g. to write it with the writers of `Grid`. The border and the nodes inside of
+    /// fixed boxes are fixed
+    pub fn to_grid(&self, level: u32) -> Grid {
+        let factor = (1usize << level) as f64;
+        let (width, height) = ((self.columns << level) + 1, (self.rows << level) + 1);
+        let mut nodes = Vec::with_capacity(width * height);
+        let mut fixed = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for column in 0..width {
+                let (c, r) = (column as f64 / factor, row as f64 / factor);
+                let border = column == 0 || row == 0 || column == width - 1 || row == height - 1;
+                let value = if border {
+                    let (x, y) = self.scale.to_external(c, r);
+                    Some(self.boundary.potential(x, y))
+                } else { self.electrode_potential(c, r) };
+                fixed.push(value.is_some());
+                nodes.push(value.unwrap_or_else(|| self.sample_internal(c, r)));
+            }
+        }
+        let mut scale = self.scale.clone();
+        scale.x.spacing /= factor;
+        scale.y.spacing /= factor;
+        Grid::from_parts(nodes, width, &fixed, scale)
+    }
+
+    /// potential of the last fixed box containing the point (internal coordinates)
+    fn electrode_potential(&self, column: f64, row: f64) -> Option<f64> {
+        self.electrodes.iter().rev().find(|&&(columns, rows, _)| {
+            column >= columns.0 && column < columns.1 && row >= rows.0 && row < rows.1
+        }).map(|&(_, _, potential)| potential)
+    }
+
+    /// width of a cell of `level` in node spacings of the coarse grid
+    fn size(level: u32) -> f64 {
+        0.5f64.powi(level as i32)
+    }
+
+    /// internal coordinates of the center of cell `i`
+    fn center(&self, i: usize) -> (f64, f64) {
+        let cell = &self.cells[i];
+        let size = AdaptiveGrid::size(cell.level);
+        ((cell.column as f64 + 0.5) * size, (cell.row as f64 + 0.5) * size)
+    }
+
+    /// adds a leaf, fixed if its center is inside of a fixed box; returns its index
+    fn add_cell(&mut self, level: u32, column: usize, row: usize, value: f64) -> usize {
+        let size = AdaptiveGrid::size(level);
+        let electrode = self.electrode_potential((column as f64 + 0.5) * size,
+                                                 (row as f64 + 0.5) * size);
+        self.cells.push(Cell { level, column, row, value: electrode.unwrap_or(value),
+            fixed: electrode.is_some(), children: None });
+        self.index.insert((level, column, row), self.cells.len() - 1);
+        self.cells.len() - 1
+    }
+
+    /// splits leaf `i` into four children (`children[2 * dy + dx]`) with its value
+    fn split(&mut self, i: usize) {
+        let (level, column, row, value) = {
+            let cell = &self.cells[i];
+            (cell.level + 1, cell.column * 2, cell.row * 2, cell.value)
+        };
+        let mut children = [0; 4];
+        for (k, child) in children.iter_mut().enumerate() {
+            *child = self.add_cell(level, column + k % 2, row + k / 2, value);
+        }
+        self.cells[i].children = Some(children);
+    }
+
+    /// the neighbours of leaf `i` in `DIRECTIONS[direction]`
+    fn neighbours(&self, i: usize, direction: usize) -> Neighbours {
+        let cell = &self.cells[i];
+        let (d_column, d_row) = DIRECTIONS[direction];
+        let (column, row) = (cell.column as isize + d_column, cell.row as isize + d_row);
+        if column < 0 || row < 0 || column >= (self.columns << cell.level) as isize ||
+            row >= (self.rows << cell.level) as isize {
+            let (c, r) = self.center(i);
+            let half = AdaptiveGrid::size(cell.level) / 2.0;
+            let (x, y) = self.scale.to_external(c + d_column as f64 * half,
+                                                r + d_row as f64 * half);
+            return Neighbours::Boundary(self.boundary.potential(x, y));
+        }
+        let (mut level, mut column, mut row) = (cell.level, column as usize, row as usize);
+        // level 0 covers everything, so this ends at the latest there
+        loop {
+            if let Some(&j) = self.index.get(&(level, column, row)) {
+                return Neighbours::Cells(match self.cells[j].children {
+                    None => vec![j],
+                    Some(children) => AdaptiveGrid::facing(direction).iter()
+                        .map(|&k| children[k]).collect(),
+                });
+            }
+            level -= 1;
+            column /= 2;
+            row /= 2;
+        }
+    }
+
+    /// the children of a neighbour in `DIRECTIONS[direction]` that touch the cell looking at it
+    fn facing(direction: usize) -> [usize; 2] {
+        match direction {
+            0 => [1, 3],
+            1 => [0, 2],
+            2 => [2, 3],
+            _ => [0, 1],
+        }
+    }
+
+    /// the physical distance from the center of leaf `i` to the potential on the other side of
+    /// its face in `direction` and the length of the face. The potential of the border and of
+    /// fixed cells (`neighbour` is `None` or fixed) sits on the face, the one of dynamic cells at
+    /// their center
+    fn face(&self, i: usize, direction: usize, neighbour: Option<usize>) -> (f64, f64) {
+        let (normal, tangential) = if direction < 2 { (self.scale.x.spacing, self.scale.y.spacing) }
+            else { (self.scale.y.spacing, self.scale.x.spacing) };
+        let size = AdaptiveGrid::size(self.cells[i].level);
+        match neighbour {
+            Some(j) if !self.cells[j].fixed => {
+                let other = AdaptiveGrid::size(self.cells[j].level);
+                ((size + other) / 2.0 * normal, size.min(other) * tangential)
+            }
+            Some(j) => {
+                let other = AdaptiveGrid::size(self.cells[j].level);
+                (size / 2.0 * normal, size.min(other) * tangential)
+            }
+            None => (size / 2.0 * normal, size * tangential),
+        }
+    }
+
+    /// the slopes of the potential from the center of leaf `i` outwards through its four faces,
+    /// averaged over the neighbours of a face
+    fn outward_slopes(&self, i: usize) -> [f64; 4] {
+        let cell = &self.cells[i];
+        let mut slopes = [0.0; 4];
+        for (direction, slope) in slopes.iter_mut().enumerate() {
+            *slope = match self.neighbours(i, direction) {
+                Neighbours::Boundary(potential) => {
+                    let (distance, _) = self.face(i, direction, None);
+                    (potential - cell.value) / distance
+                }
+                Neighbours::Cells(neighbours) => neighbours.iter().map(|&j| {
+                    let (distance, _) = self.face(i, direction, Some(j));
+                    (self.cells[j].value - cell.value) / distance
+                }).sum::<f64>() / neighbours.len() as f64,
+            };
+        }
+        slopes
+    }
+
+    /// error estimate of every dynamic leaf: the jump of the normal derivative across the cell
+    /// times its size
+    fn error_estimates(&self) -> Vec<(usize, f64)> {
+        (0..self.cells.len()).filter(|&i| self.cells[i].children.is_none() &&
+            !self.cells[i].fixed).map(|i| {
+            let slopes = self.outward_slopes(i);
+            let size = AdaptiveGrid::size(self.cells[i].level);
+            let jump_x = (slopes[0] + slopes[1]).abs() * size * self.scale.x.spacing;
+            let jump_y = (slopes[2] + slopes[3]).abs() * size * self.scale.y.spacing;
+            (i, jump_x.max(jump_y))
+        }).collect()
+    }
+
+    /// the potential at internal coordinates inside of the grid
+    fn sample_internal(&self, column: f64, row: f64) -> f64 {
+        let mut i = self.index[&(0, (column as usize).min(self.columns - 1),
+                                 (row as usize).min(self.rows - 1))];
+        while let Some(children) = self.cells[i].children {
+            let (c, r) = self.center(i);
+            i = children[(column >= c) as usize + 2 * (row >= r) as usize];
+        }
+        let cell = &self.cells[i];
+        if cell.fixed { return cell.value; }
+        // central slopes in internal coordinates
+        let slopes = self.outward_slopes(i);
+        let gradient_column = (slopes[1] - slopes[0]) / 2.0 * self.scale.x.spacing;
+        let gradient_row = (slopes[3] - slopes[2]) / 2.0 * self.scale.y.spacing;
+        let (c, r) = self.center(i);
+        cell.value + gradient_column * (column - c) + gradient_row * (row - r)
+    }
+
+    /// the equations of all dynamic leaves
+    fn system(&self) -> System {
+        let mut system = System { dynamic: Vec::new(), start: vec![0], links: Vec::new(),
+            constant: Vec::new(), diagonal: Vec::new() };
+        for (i, cell) in self.cells.iter().enumerate() {
+            if cell.children.is_some() || cell.fixed { continue; }
+            let (mut constant, mut diagonal) = (0.0, 0.0);
+            for direction in 0..4 {
+                match self.neighbours(i, direction) {
+                    Neighbours::Boundary(potential) => {
+                        let (distance, length) = self.face(i, direction, None);
+                        constant += length / distance * potential;
+                        diagonal += length / distance;
+                    }
+                    Neighbours::Cells(neighbours) => for j in neighbours {
+                        let (distance, length) = self.face(i, direction, Some(j));
+                        if self.cells[j].fixed {
+                            constant += length / distance * self.cells[j].value;
+                        } else {
+                            system.links.push((j, length / distance));
+                        }
+                        diagonal += length / distance;
+                    },
+                }
+            }
+            system.dynamic.push(i);
+            system.start.push(system.links.len());
+            system.constant.push(constant);
+            system.diagonal.push(diagonal);
+        }
+        system
+    }
+
+    /// iterates (successive over relaxation) until the max delta is below `accepted_delta`,
+    /// returns the number of iterations and whether they converged
+    fn solve(&mut self, accepted_delta: f64, over_relaxation: f64) -> (usize, bool) {
+        let system = self.system();
+        let cells = &mut self.cells;
+        iterate(accepted_delta, Some(""), |_| {
+            let mut max_delta: f64 = 0.0;
+            for (k, &i) in system.dynamic.iter().enumerate() {
+                let sum: f64 = system.links[system.start[k]..system.start[k + 1]].iter()
+                    .map(|&(j, weight)| weight * cells[j].value).sum();
+                let delta = cells[i].value - (sum + system.constant[k]) / system.diagonal[k];
+                cells[i].value -= over_relaxation * delta;
+                max_delta = max_delta.max(delta.abs());
+            }
+            Some(max_delta)
+        })
+    }
+
+    /// the width and height of the smallest cells
+    fn finest_spacing(&self) -> (f64, f64) {
+        let factor = (1u64 << self.max_level()) as f64;
+        (self.scale.x.spacing / factor, self.scale.y.spacing / factor)
+    }
+
+    /// splits the cells with the biggest error estimates (of those below the max level) and then
+    /// every cell that is more than one level coarser than a neighbour, returns the number of
+    /// split cells
+    fn refine(&mut self, refinement: &Refinement) -> usize {
+        // cells at the max level (next to singular corners) must not hold back all others
+        let estimates: Vec<(usize, f64)> = self.error_estimates().into_iter()
+            .filter(|&(i, _)| self.cells[i].level < refinement.max_level).collect();
+        let max = estimates.iter().fold(0.0, |max: f64, &(_, error)| max.max(error));
+        let threshold = refinement.target.max(refinement.fraction * max);
+        let marked: Vec<usize> = estimates.iter().filter(|&&(_, error)| error > threshold)
+            .map(|&(i, _)| i).collect();
+        let mut split = marked.len();
+        for i in marked {
+            self.split(i);
+        }
+
+        loop {
+            let unbalanced: Vec<usize> = (0..self.cells.len())
+                .filter(|&i| self.cells[i].children.is_none() && self.unbalanced(i)).collect();
+            if unbalanced.is_empty() { break; }
+            split += unbalanced.len();
+            for i in unbalanced {
+                self.split(i);
+            }
+        }
+        split
+    }
+
+    /// true if a neighbour of leaf `i` is split into cells that are split again
+    fn unbalanced(&self, i: usize) -> bool {
+        (0..4).any(|direction| match self.neighbours(i, direction) {
+            Neighbours::Boundary(_) => false,
+            Neighbours::Cells(neighbours) =>
+                neighbours.iter().any(|&j| self.cells[j].children.is_some()),
+        })
+    }
+}
@@ -0,0 +1,26 @@
+extern crate numeric_laplace;
+
+use numeric_laplace::*;
+
+fn main() {
+    let yellow = FixedBox { x: -5, y: 5, width: 10, height: 10, potential: 100.0 };
+    let blue_top = FixedBox { x: 30, y: 20, width: 5, height: 18, potential: 0.0 };
+    let blue_bottom = FixedBox { x: 30, y: -2, width: 5, height: 18, potential: 0.0 };
+    // the same task as in "main", the resolution is set by the study
+    let task = GridBuilder::new()
+        .size(50, 50)
+        .origin(-10.0, 25.0)
+        .invert(false, true)
+        .electrodes(&[yellow, blue_top, blue_bottom]);
+
+    // points in the gap between the electrodes and far away from them
+    let probes = [(10.0, 0.0), (20.0, 0.0), (32.0, 0.0), (0.0, -20.0)];
+
+    let study = convergence_study(&task, &[2, 4, 8], &probes, 1e-6, 1.9)
+        .expect("Invalid task or probe outside of the grid!");
+    for probe in study.probes.iter() {
+        println!("({}, {}): {:?}, order {:.2}, extrapolated {:.4}, GCI {:.3} %", probe.x, probe.y,
+                 probe.values, probe.observed_order, probe.extrapolated, probe.gci * 100.0);
+    }
+    study.to_csv("convergence.csv").expect("Could not write convergence file!");
+}
@@ -0,0 +1,488 @@
+//! # Grid Builder
+//!
+//! describing a task step by step instead of with one long list of parameters. Everything that is
+//! not set has a sensible default: 1 node per unit, the origin at 0, no inverted axes, millimeters
+//! and volts, a planar geometry, the 5-point stencil, no electrodes, no source, a border at 0 and
+//! random noise as the initial guess. Only the size is required.
+
+use super::{box_extent, smallest_step, Axis, FixedBox, Grid, GridError, Scale};
+use grading::Grading;
+use conductor::Conductor;
+use open::OpenBoundary;
+use current::Conduction;
+use stencil::{Discretization, Geometry, StencilKind};
+use initial_guess::InitialGuess;
+use units::{LengthUnit, PotentialUnit, Units};
+
+/// The potential of the border nodes that are not covered by a fixed box
+///
+/// * `Potential` - the whole border has the given potential
+/// * `Function` - the potential of every border node is the function evaluated at its external
+///   coordinates (x, y)
+/// * `Open` - the border follows the field of the electrodes towards infinity, it starts at 0.
+///   `evaluate`, `evaluate_multi_thread`, `evaluate_open` and `solve_cascade` solve it in passes,
+///   `evaluate_with_checkpoints` rejects it
+#[derive(Debug, Clone, Copy)]
+pub enum BoundaryCondition {
+    Potential(f64),
+    Function(fn(f64, f64) -> f64),
+    Open(OpenBoundary),
+}
+
+impl BoundaryCondition {
+    /// potential of the border node at the external coordinates `x` and `y`
+    pub fn potential(&self, x: f64, y: f64) -> f64 {
+        match *self {
+            BoundaryCondition::Potential(potential) => potential,
+            BoundaryCondition::Function(function) => function(x, y),
+            BoundaryCondition::Open(_) => 0.0,
+        }
+    }
+}
+
+/// Where the nodes of one axis are
+///
+/// * `Even` - evenly spaced with the spacing of the scale (or graded if the axis of the scale is)
+/// * `Graded` - graded over the size of the grid, starting at the origin of the scale
+/// * `Coordinates` - at the given external coordinates, they replace origin and inversion
+#[derive(Debug, Clone)]
+pub(crate) enum AxisNodes {
+    Even,
+    Graded(Grading),
+    Coordinates(Vec<f64>),
+}
+
+/// Collects the description of a task, `build` turns it into a `Grid`. The builder is not
+/// consumed by `build` so it can create several grids of the same task
+#[derive(Clone)]
+pub struct GridBuilder<'g> {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) scale: Scale,
+    pub(crate) electrodes: Vec<FixedBox>,
+    pub(crate) seed: Option<[u8; 32]>,
+    pub(crate) boundary: BoundaryCondition,
+    pub(crate) initial_guess: Option<InitialGuess<'g>>,
+    pub(crate) x_nodes: AxisNodes,
+    pub(crate) y_nodes: AxisNodes,
+    /// only every `coarsening`th node of both axes is used (for coarse levels of a solve)
+    pub(crate) coarsening: usize,
+    pub(crate) geometry: Geometry,
+    pub(crate) stencil: StencilKind,
+    pub(crate) conductors: Vec<Conductor>,
+    pub(crate) conduction: Option<Conduction>,
+    pub(crate) source: Option<fn(f64, f64) -> f64>,
+}
+
+impl<'g> Default for GridBuilder<'g> {
+    fn default() -> GridBuilder<'g> {
+        GridBuilder::new()
+    }
+}
+
+impl<'g> GridBuilder<'g> {
+    pub fn new() -> GridBuilder<'g> {
+        GridBuilder {
+            width: 0,
+            height: 0,
+            scale: Scale {
+                x: Axis { origin: 0.0, spacing: 1.0, invert: false, graded: None },
+                y: Axis { origin: 0.0, spacing: 1.0, invert: false, graded: None },
+                units: Units::default(),
+            },
+            electrodes: Vec::new(),
+            seed: None,
+            boundary: BoundaryCondition::Potential(0.0),
+            initial_guess: None,
+            x_nodes: AxisNodes::Even,
+            y_nodes: AxisNodes::Even,
+            coarsening: 1,
+            geometry: Geometry::Planar,
+            stencil: StencilKind::FivePoint,
+            conductors: Vec::new(),
+            conduction: None,
+            source: None,
+        }
+    }
+
+    /// size of the grid in units, axes with explicit node coordinates ignore it
+    pub fn size(mut self, width: usize, height: usize) -> GridBuilder<'g> {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// the same number of nodes per unit in both directions
+    pub fn resolution(self, nodes_per_unit: usize) -> GridBuilder<'g> {
+        let spacing = 1.0 / nodes_per_unit as f64;
+        self.spacing(spacing, spacing)
+    }
+
+    /// distance between neighbouring nodes in x and y direction, only used by evenly spaced axes
+    pub fn spacing(mut self, dx: f64, dy: f64) -> GridBuilder<'g> {
+        self.scale.x.spacing = dx;
+        self.scale.y.spacing = dy;
+        self
+    }
+
+    /// external coordinates of the first node (column 0 and row 0), the top left one unless the
+    /// axes are inverted
+    pub fn origin(mut self, x: f64, y: f64) -> GridBuilder<'g> {
+        self.scale.x.origin = x;
+        self.scale.y.origin = y;
+        self
+    }
+
+    /// whether the x axis goes from right to left and the y axis from top to bottom
+    pub fn invert(mut self, invert_x: bool, invert_y: bool) -> GridBuilder<'g> {
+        self.scale.x.invert = invert_x;
+        self.scale.y.invert = invert_y;
+        self
+    }
+
+    /// units of the coordinates and the potential
+    pub fn units(mut self, length: LengthUnit, potential: PotentialUnit) -> GridBuilder<'g> {
+        self.scale.units = Units { length, potential };
+        self
+    }
+
+    /// sets spacing, origin, axis inversion and units at once
+    pub fn scale(mut self, scale: Scale) -> GridBuilder<'g> {
+        self.scale = scale;
+        self
+    }
+
+    /// Grades the axes that get a `Grading` (the others stay evenly spaced), the nodes span the
+    /// whole size including both ends. Use the edges of the electrodes as lines
+    pub fn grade(mut self, x: Option<Grading>, y: Option<Grading>) -> GridBuilder<'g> {
+        if let Some(grading) = x { self.x_nodes = AxisNodes::Graded(grading); }
+        if let Some(grading) = y { self.y_nodes = AxisNodes::Graded(grading); }
+        self
+    }
+
+    /// Places the nodes at the given external coordinates (columns `x`, rows `y`), they have to
+    /// be strictly increasing or decreasing (inverted axis). Origin, inversion and size of the
+    /// builder are ignored for these axes
+    pub fn node_coordinates(mut self, x: &[f64], y: &[f64]) -> GridBuilder<'g> {
+        self.x_nodes = AxisNodes::Coordinates(x.to_vec());
+        self.y_nodes = AxisNodes::Coordinates(y.to_vec());
+        self
+    }
+
+    /// keeps only every `factor`th node of both axes, used for the coarse levels of a solve
+    pub(crate) fn coarsen(mut self, factor: usize) -> GridBuilder<'g> {
+        self.coarsening *= factor;
+        self
+    }
+
+    /// the scale of the grid with the nodes of both axes in place
+    pub(crate) fn resolved_scale(&self) -> Result<Scale, GridError> {
+        let x = self.resolved_axis(&self.scale.x, &self.x_nodes, self.width, "x")?;
+        let y = self.resolved_axis(&self.scale.y, &self.y_nodes, self.height, "y")?;
+        Ok(Scale { x, y, units: self.scale.units })
+    }
+
+    fn resolved_axis(&self, axis: &Axis, nodes: &AxisNodes, units: usize, name: &str)
+        -> Result<Axis, GridError> {
+        let mut axis = match *nodes {
+            AxisNodes::Even => axis.clone(),
+            AxisNodes::Graded(ref grading) => {
+                if units == 0 {
+                    return Err(GridError::ZeroSize { width: self.width, height: self.height });
+                }
+                if !axis.origin.is_finite() {
+                    return Err(GridError::InvalidScale(format!(
+                        "the {} axis needs a finite origin", name)));
+                }
+                let positions = grading.positions(axis, units as f64, name)
+                    .map_err(GridError::InvalidScale)?;
+                let mut graded = axis.clone();
+                graded.spacing = smallest_step(&positions);
+                graded.graded = Some(positions);
+                graded
+            }
+            AxisNodes::Coordinates(ref coordinates) => {
+                let mut axis = Axis::from_coordinates(coordinates, name)
+                    .map_err(GridError::InvalidScale)?;
+                // coarsening evenly spaced coordinates by a factor that does not divide their
+                // cells would drop the last node, as a graded axis it is kept like the last
+                // coordinate of graded ones
+                let cells = coordinates.len() - 1;
+                if axis.graded.is_none() && cells % self.coarsening != 0 {
+                    axis.graded = Some((0..cells + 1).map(|i| i as f64 * axis.spacing).collect());
+                }
+                axis
+            }
+        };
+        if self.coarsening > 1 {
+            axis = axis.coarsened(self.coarsening);
+        }
+        if !(axis.spacing > 0.0 && axis.spacing.is_finite() && axis.origin.is_finite()) {
+            return Err(GridError::InvalidScale(format!(
+                "the {} axis needs a positive spacing and a finite origin", name)));
+        }
+        if let Some(ref positions) = axis.graded {
+            let increasing = positions.windows(2).all(|p| p[1] > p[0] && p[1].is_finite());
+            if positions.len() < 2 || positions[0] != 0.0 || !increasing {
+                return Err(GridError::InvalidScale(format!(
+                    "the node positions of the {} axis have to start at 0 and increase strictly",
+                    name)));
+            }
+        }
+        Ok(axis)
+    }
+
+    /// Planar or axisymmetric, in axisymmetric grids x is the radius and y is z
+    pub fn geometry(mut self, geometry: Geometry) -> GridBuilder<'g> {
+        self.geometry = geometry;
+        self
+    }
+
+    /// the stencil every evaluation of the grid uses
+    pub fn stencil(mut self, stencil: StencilKind) -> GridBuilder<'g> {
+        self.stencil = stencil;
+        self
+    }
+
+    /// adds one fixed box
+    pub fn electrode(mut self, electrode: FixedBox) -> GridBuilder<'g> {
+        self.electrodes.push(electrode);
+        self
+    }
+
+    /// adds several fixed boxes, errors name them by their position among all added boxes
+    pub fn electrodes(mut self, electrodes: &[FixedBox]) -> GridBuilder<'g> {
+        self.electrodes.extend_from_slice(electrodes);
+        self
+    }
+
+    /// Adds a circle or polygon with a fixed potential, the nodes inside of it are fixed and the
+    /// stencils of the nodes next to it use the true distance to its boundary. Conductors may
+    /// reach outside of the grid. Errors name them by their position among all added conductors
+    pub fn conductor(mut self, conductor: Conductor) -> GridBuilder<'g> {
+        self.conductors.push(conductor);
+        self
+    }
+
+    /// Solves for the steady current through a resistive sheet with the given conductivities
+    /// instead of the potential in a uniform dielectric, the fixed boxes are the terminals. Needs
+    /// a planar grid, the 5-point stencil, no conductors and a fixed or insulating border
+    pub fn conduction(mut self, conduction: Conduction) -> GridBuilder<'g> {
+        self.conduction = Some(conduction);
+        self
+    }
+
+    /// Solves the Poisson equation `-∇²u = source` instead of the Laplace equation, the source
+    /// is evaluated at the external coordinates (x, y) of every node. For electrostatics it is
+    /// the charge density over the permittivity, for conduction the current fed into the sheet
+    /// per area
+    pub fn source(mut self, source: fn(f64, f64) -> f64) -> GridBuilder<'g> {
+        self.source = Some(source);
+        self
+    }
+
+    /// seed of the random initial guess, without one every grid starts with different noise
+    pub fn seed(mut self, seed: [u8; 32]) -> GridBuilder<'g> {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// potential of the border
+    pub fn boundary(mut self, boundary: BoundaryCondition) -> GridBuilder<'g> {
+        self.boundary = boundary;
+        self
+    }
+
+    /// how the dynamic nodes are filled, random noise (with `seed`) if this is not set
+    pub fn initial_guess(mut self, initial_guess: InitialGuess<'g>) -> GridBuilder<'g> {
+        self.initial_guess = Some(initial_guess);
+        self
+    }
+
+    /// Creates the grid. Fails if the grid is empty, the scale is invalid (including a negative
+    /// or inverted radius of an axisymmetric grid, or one the stencil can not be used with), a
+    /// fixed box is not completely inside of the grid, two overlapping fixed boxes have
+    /// different potentials, a conductor has an invalid shape or covers a node that is fixed to
+    /// a different potential already, an open border does not enclose the electrodes, the
+    /// conduction can not be used or a coarse initial guess has a factor of 0 or an over
+    /// relaxation outside of (0, 2)
+    pub fn build(&self) -> Result<Grid, GridError> {
+        if let Some(InitialGuess::Coarse { factor, over_relaxation, .. }) = self.initial_guess {
+            if factor == 0 || !(over_relaxation > 0.0 && over_relaxation < 2.0) {
+                return Err(GridError::InvalidInitialGuess(format!(
+                    "a coarse guess needs a factor of at least 1 and an over relaxation between \
+                     0 and 2, not {} and {}", factor, over_relaxation)));
+            }
+        }
+        let scale = self.resolved_scale()?;
+        if self.geometry == Geometry::Axisymmetric && (scale.x.invert ||
+            scale.x.origin < -scale.x.spacing * 1e-6) {
+            return Err(GridError::InvalidScale(String::from(
+                "the radius (x axis) of an axisymmetric grid can not be inverted or negative")));
+        }
+        self.stencil.check(&scale, self.geometry).map_err(GridError::InvalidScale)?;
+        for (index, conductor) in self.conductors.iter().enumerate() {
+            conductor.shape.check()
+                .map_err(|reason| GridError::InvalidConductor { index, reason })?;
+        }
+        if let Some(ref conduction) = self.conduction {
+            conduction.check(self.geometry, self.stencil).map_err(GridError::InvalidConduction)?;
+            let open = matches!(self.boundary, BoundaryCondition::Open(_));
+            if !self.conductors.is_empty() || open {
+                return Err(GridError::InvalidConduction(String::from(
+                    "current flow can not be combined with conductors or an open border")));
+            }
+        }
+        let (units_wide, units_high) = (self.width, self.height);
+        // nodes in each direction, on evenly spaced axes the last one is one spacing short of the
+        // size
+        let node_count = |axis: &Axis, nodes: &AxisNodes, units: usize| {
+            match (&axis.graded, nodes) {
+                (Some(positions), _) => positions.len() as f64,
+                // evenly spaced coordinates keep their number of nodes
+                (None, AxisNodes::Coordinates(coordinates)) =>
+                    ((coordinates.len() - 1) / self.coarsening + 1) as f64,
+                (None, _) => (units as f64 / axis.spacing).round(),
+            }
+        };
+        let width = node_count(&scale.x, &self.x_nodes, units_wide);
+        let height = node_count(&scale.y, &self.y_nodes, units_high);
+        if width < 1.0 || height < 1.0 {
+            return Err(GridError::ZeroSize { width: units_wide, height: units_high });
+        }
+        if width * height > isize::MAX as f64 {
+            return Err(GridError::InvalidScale(format!(
+                "{} x {} units with a spacing of {} x {} are too many nodes", units_wide,
+                units_high, scale.x.spacing, scale.y.spacing)));
+        }
+        let (width, height) = (width as usize, height as usize);
+
+        // internal extent of every box, columns and rows as half open ranges
+        let mut extents = Vec::with_capacity(self.electrodes.len());
+        for (index, fixed_box) in self.electrodes.iter().enumerate() {
+            let (columns, rows) = box_extent(fixed_box, &scale);
+            if columns.0 < 0 || rows.0 < 0 || columns.1 > width as isize ||
+                rows.1 > height as isize {
+                return Err(GridError::OutOfBounds { index, fixed_box: fixed_box.clone() });
+            }
+            extents.push((columns, rows));
+        }
+        for (first, &(columns, rows)) in extents.iter().enumerate() {
+            for (second, &(other_columns, other_rows)) in extents.iter().enumerate()
+                .skip(first + 1) {
+                if self.electrodes[first].potential == self.electrodes[second].potential {
+                    continue;
+                }
+                let column = columns.0.max(other_columns.0);
+                let row = rows.0.max(other_rows.0);
+                if column < columns.1.min(other_columns.1) && row < rows.1.min(other_rows.1) {
+                    let (x, y) = scale.to_external(column as f64, row as f64);
+                    return Err(GridError::OverlapConflict { first, second, x, y });
+                }
+            }
+        }
+
+        let mut nodes = vec![0.0; width*height];
+        let mut  fixed_nodes_indices = Vec::with_capacity(width*height);
+
+        // adding all indices of the border to the ´fixed_nodes_indices´ array, an insulating
+        // border is dynamic
+        let insulating = self.conduction.as_ref()
+            .map_or(false, |conduction| conduction.insulating_border);
+        let bottom_border_start_index = width * (height-1);
+        for i in (0..width).filter(|_| !insulating) {
+            //top border
+            fixed_nodes_indices.push(i);
+            //bottom border
+            fixed_nodes_indices.push(bottom_border_start_index + i);
+        }
+        // the first column of an axisymmetric grid may be the axis instead of a border
+        let left_border = !self.geometry.has_axis(&scale);
+        for i in (0..height).filter(|_| !insulating) {
+            //left border
+            if left_border {
+                fixed_nodes_indices.push(i*width);
+            }
+            //right border
+            fixed_nodes_indices.push(i*width+width-1);
+        }
+        for &i in fixed_nodes_indices.iter() {
+            let (x, y) = scale.to_external((i % width) as f64, (i / width) as f64);
+            nodes[i] = self.boundary.potential(x, y);
+        }
+        let border_count = fixed_nodes_indices.len();
+
+        // adding all indices of the fixed boxes to the ´fixed_nodes_indices´ array
+        for (fixed_box, &(columns, rows)) in self.electrodes.iter().zip(extents.iter()) {
+            for row in rows.0 as usize..rows.1 as usize {
+                for column in columns.0 as usize..columns.1 as usize {
+                    let index = column + row * width;
+                    fixed_nodes_indices.push(index);
+                    nodes[index] = fixed_box.potential;
+                }
+            }
+        }
+
+        // the nodes inside of the conductors, they must not contradict the boxes or each other
+        if !self.conductors.is_empty() {
+            let mut potentials = vec![None; nodes.len()];
+            for (fixed_box, &(columns, rows)) in self.electrodes.iter().zip(extents.iter()) {
+                for row in rows.0 as usize..rows.1 as usize {
+                    for column in columns.0 as usize..columns.1 as usize {
+                        potentials[column + row * width] = Some(fixed_box.potential);
+                    }
+                }
+            }
+            for (index, conductor) in self.conductors.iter().enumerate() {
+                for i in conductor.covered_nodes(&scale, width, height) {
+                    if potentials[i].map_or(false, |potential| potential != conductor.potential) {
+                        let (x, y) = scale.to_external((i % width) as f64, (i / width) as f64);
+                        return Err(GridError::ConductorConflict { index, x, y });
+                    }
+                    potentials[i] = Some(conductor.potential);
+                    fixed_nodes_indices.push(i);
+                    nodes[i] = conductor.potential;
+                }
+            }
+        }
+
+        let open = match self.boundary {
+            BoundaryCondition::Open(ref open) => Some(open.border(
+                &scale, self.geometry, width, height, &fixed_nodes_indices[border_count..])
+                .map_err(GridError::InvalidOpenBoundary)?),
+            _ => None,
+        };
+
+        //"inverting" the `fixed_nodes_indices` vector
+        // this is the fastest method  i came up with if done wrong this will take a long time
+        // (i.e. using .contains())
+        fixed_nodes_indices.sort_unstable();
+        fixed_nodes_indices.dedup();
+        let mut dynamic_nodes_indices =
+            Vec::with_capacity(nodes.len()-fixed_nodes_indices.len());
+        let mut j = 0;
+        for i in 0..nodes.len() {
+            if fixed_nodes_indices.get(j) != Some(&i) {
+                dynamic_nodes_indices.push(i);
+            } else { j += 1; }
+        }
+
+        let source = match self.source {
+            Some(source) => (0..nodes.len()).map(|i| {
+                let (x, y) = scale.to_external((i % width) as f64, (i / width) as f64);
+                source(x, y)
+            }).collect(),
+            None => Vec::new(),
+        };
+        let discretization = Discretization { geometry: self.geometry, stencil_kind: self.stencil,
+                                             conductors: self.conductors.clone(),
+                                             conduction: self.conduction.clone(), source };
+        let mut grid = Grid::assemble(nodes, width, dynamic_nodes_indices, scale, discretization);
+        grid.open = open;
+        match self.initial_guess {
+            Some(ref guess) => grid.apply_initial_guess(guess, self)?,
+            None => grid.apply_initial_guess(&InitialGuess::Random(self.seed), self)?,
+        }
+        Ok(grid)
+    }
+}
@@ -0,0 +1,63 @@
+//! # Resolution Cascade
+//!
+//! solving on a coarse grid first and using the result as the initial guess of a finer one. The
+//! coarse levels converge in few (cheap) iterations and the fine level starts close to the
+//! solution, which saves a lot of time for small node spacings.
+
+use super::{iterate, Grid, GridError};
+use builder::GridBuilder;
+use initial_guess::InitialGuess;
+
+impl Grid {
+    /// Solves the task described by `task` (its initial guess is ignored) with every ..., 4th,
+    /// 2nd and finally every node, starting with the biggest factor that keeps the spacing at
+    /// most one unit (everywhere on graded axes). Every level rasterizes the fixed boxes again
+    /// and starts from the interpolated solution of the previous one, every level iterates until
+    /// the max delta is below `accepted_delta` (an open border in passes like `evaluate_open`).
+    /// Fails for the same reasons as `GridBuilder::build` and with `NotConverged` once a level
+    /// does not converge
+    pub fn solve_cascade(task: &GridBuilder, accepted_delta: f64, over_relaxation: f64)
+        -> Result<Grid, GridError> {
+        let scale = task.resolved_scale()?;
+        let spacing = scale.x.max_spacing().max(scale.y.max_spacing());
+        let mut factor = 1;
+        while spacing * (factor * 2) as f64 <= 1.0 + 1e-9 {
+            factor *= 2;
+        }
+
+        let mut previous: Option<Grid> = None;
+        while factor > 1 {
+            let mut grid = Grid::cascade_level(task.clone().coarsen(factor), previous.as_ref())?;
+            grid.solve_level(accepted_delta, over_relaxation)?;
+            previous = Some(grid);
+            factor /= 2;
+        }
+
+        let mut grid = Grid::cascade_level(task.clone(), previous.as_ref())?;
+        grid.solve_level(accepted_delta, over_relaxation)?;
+        Ok(grid)
+    }
+
+    /// builds one level of the cascade starting from `previous` (or the harmonic guess)
+    fn cascade_level(task: GridBuilder, previous: Option<&Grid>) -> Result<Grid, GridError> {
+        let guess = match previous {
+            Some(grid) => InitialGuess::Existing(grid),
+            None => InitialGuess::Harmonic,
+        };
+        task.initial_guess(guess).build()
+    }
+
+    /// iterates until the max delta is below `accepted_delta` (in passes like `evaluate_open`
+    /// if the border is open) and prints how long it took, fails if the level does not converge
+    fn solve_level(&mut self, accepted_delta: f64, over_relaxation: f64)
+        -> Result<(), GridError> {
+        let spacing = (self.scale.x.spacing, self.scale.y.spacing);
+        let label = format!("spacing {} x {}: ", spacing.0, spacing.1);
+        match self.open_passes(accepted_delta, |grid| {
+            iterate(accepted_delta, Some(&label), |_| Some(grid.sweep(over_relaxation))).1
+        }) {
+            (_, true) => Ok(()),
+            (_, false) => Err(GridError::NotConverged { spacing }),
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! # Checkpoints
+//!
+//! a compact binary format to save a running solve and continue it later. A checkpoint holds the
+//! nodes, the fixed mask, the `Scale` and the state of the solver (iteration, over relaxation and
+//! the max delta of every iteration so far). Since the nodes are stored bit for bit and the nodes
+//! are always updated in the same order, a resumed solve follows exactly the same trajectory as
+//! one that was never interrupted.
+//!
+//! Layout (all numbers little endian):
+//!
+//! * magic `NLCHKPT\0` and the format version (u32)
+//! * scale: for the x and then the y axis the origin and spacing (f64), the number of node
+//!   positions (u64, 0 for evenly spaced axes) and the positions of graded axes (f64); then the
+//!   inversion flags (u8, bit 0 x, bit 1 y), length unit (u8: 0 µm, 1 mm, 2 m), potential unit
+//!   (u8: 0 V, 1 kV), geometry (u8: 0 planar, 1 axisymmetric) and stencil (u8: 0 5-point,
+//!   1 9-point)
+//! * the number of conductors (u64), for each its potential (f64) and shape: 0 (u8) and the
+//!   center and radius (f64) of a circle or 1 (u8), the number of corners (u64) and their x and y
+//!   (f64) of a polygon
+//! * conduction: 0 (u8) for none or 1 (u8), the background conductivity (f64), whether the
+//!   border is insulating (u8), the number of regions (u64) and for each its x and y (i64), width
+//!   and height (u64) and conductivity (f64)
+//! * open border: 0 (u8) for a fixed border or 1 (u8), the x and y of the center (f64), the
+//!   order and the inset (u64)
+//! * width and height (u64), the nodes (f64) and the fixed mask (one u8 per node)
+//! * source: 0 (u8) for none or 1 (u8) and the source at every node (f64)
+//! * iteration (u64), over relaxation (f64), number of residuals (u64) and the residuals (f64)
+//! * crc32 of everything before it (u32)
+//!
+//! Checkpoints of any other version are rejected.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{iterate, Axis, Conduction, Conductor, ConductivityBox, Geometry, Grid, OpenBoundary,
+            Scale, Shape, StencilKind};
+use stencil::Discretization;
+use units::{LengthUnit, PotentialUnit, Units};
+use checksum::crc32;
+
+const MAGIC: &[u8; 8] = b"NLCHKPT\0";
+const VERSION: u32 = 2;
+
+/// State of an iterative solve besides the nodes
+///
+/// # Fields
+///
+/// * `iteration` - number of iterations done so far
+/// * `over_relaxation` - over relaxation factor of the solve
+/// * `residuals` - the max delta of every iteration done so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolverState {
+    pub iteration: usize,
+    pub over_relaxation: f64,
+    pub residuals: Vec<f64>,
+}
+
+impl SolverState {
+    /// state of a solve that has not started yet
+    pub fn new(over_relaxation: f64) -> SolverState {
+        SolverState { iteration: 0, over_relaxation, residuals: Vec::new() }
+    }
+}
+
+/// A checkpoint read from a file, `grid` is the grid to continue with
+pub struct Checkpoint {
+    pub grid: Grid,
+    pub state: SolverState,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// reads the values of a checkpoint one after another
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, length: usize) -> io::Result<&'b [u8]> {
+        if self.position + length > self.bytes.len() {
+            return Err(invalid_data(String::from("checkpoint is truncated")));
+        }
+        self.position += length;
+        Ok(&self.bytes[self.position - length..self.position])
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    /// a length or count, checked against the remaining bytes so corrupt files do not cause
+    /// huge allocations
+    fn count(&mut self, bytes_per_element: usize) -> io::Result<usize> {
+        let count = self.u64()? as usize;
+        if count.saturating_mul(bytes_per_element) > self.bytes.len() - self.position {
+            return Err(invalid_data(String::from("checkpoint is truncated")));
+        }
+        Ok(count)
+    }
+}
+
+impl Checkpoint {
+    /// reads a checkpoint written by `Grid::save_checkpoint`
+    pub fn load(path: &str) -> io::Result<Checkpoint> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.len() < MAGIC.len() + 8 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data(format!("{} is not a checkpoint", path)));
+        }
+        let (content, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32(content) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2],
+                                                 checksum[3]]) {
+            return Err(invalid_data(format!("{} is corrupt (checksum mismatch)", path)));
+        }
+
+        let mut reader = Reader { bytes: content, position: MAGIC.len() };
+        let version = reader.take(4)?;
+        let version = u32::from_le_bytes([version[0], version[1], version[2], version[3]]);
+        if version != VERSION {
+            return Err(invalid_data(format!("{} has version {}, only version {} is supported",
+                                            path, version, VERSION)));
+        }
+        let mut discretization = Discretization::default();
+        let mut axes = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let (origin, spacing) = (reader.f64()?, reader.f64()?);
+            let count = reader.count(8)?;
+            let graded = match count {
+                0 => None,
+                1 => return Err(invalid_data(format!(
+                    "{} has a graded axis with a single node", path))),
+                _ => {
+                    let mut positions = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        positions.push(reader.f64()?);
+                    }
+                    Some(positions)
+                }
+            };
+            axes.push(Axis { origin, spacing, invert: false, graded });
+        }
+        let flags = reader.take(5)?;
+        let length = match flags[1] {
+            0 => LengthUnit::Micrometer,
+            1 => LengthUnit::Millimeter,
+            2 => LengthUnit::Meter,
+            unit => return Err(invalid_data(format!("{} has an unknown length unit {}",
+                                                    path, unit))),
+        };
+        let potential = match flags[2] {
+            0 => PotentialUnit::Volt,
+            1 => PotentialUnit::Kilovolt,
+            unit => return Err(invalid_data(format!(
+                "{} has an unknown potential unit {}", path, unit))),
+        };
+        discretization.geometry = match flags[3] {
+            0 => Geometry::Planar,
+            1 => Geometry::Axisymmetric,
+            value => return Err(invalid_data(format!(
+                "{} has an unknown geometry {}", path, value))),
+        };
+        discretization.stencil_kind = match flags[4] {
+            0 => StencilKind::FivePoint,
+            1 => StencilKind::NinePoint,
+            value => return Err(invalid_data(format!(
+                "{} has an unknown stencil {}", path, value))),
+        };
+        for _ in 0..reader.count(17)? {
+            let potential = reader.f64()?;
+            let shape = match reader.take(1)?[0] {
+                0 => Shape::Circle { x: reader.f64()?, y: reader.f64()?,
+                                     radius: reader.f64()? },
+                1 => {
+                    let count = reader.count(16)?;
+                    let mut corners = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        corners.push((reader.f64()?, reader.f64()?));
+                    }
+                    Shape::Polygon(corners)
+                }
+                value => return Err(invalid_data(format!(
+                    "{} has an unknown shape {}", path, value))),
+            };
+            shape.check().map_err(|message| invalid_data(format!(
+                "{}: {}", path, message)))?;
+            discretization.conductors.push(Conductor { shape, potential });
+        }
+        if reader.take(1)?[0] != 0 {
+            let background = reader.f64()?;
+            let insulating_border = reader.take(1)?[0] != 0;
+            let mut regions = Vec::new();
+            for _ in 0..reader.count(40)? {
+                regions.push(ConductivityBox {
+                    x: reader.u64()? as i64 as isize,
+                    y: reader.u64()? as i64 as isize,
+                    width: reader.u64()? as usize,
+                    height: reader.u64()? as usize,
+                    conductivity: reader.f64()?,
+                });
+            }
+            discretization.conduction =
+                Some(Conduction { background, regions, insulating_border });
+        }
+        let open = if reader.take(1)?[0] != 0 {
+            Some(OpenBoundary { center: (reader.f64()?, reader.f64()?),
+                order: reader.u64()? as usize, inset: reader.u64()? as usize })
+        } else {
+            None
+        };
+        let (mut y, mut x) = (axes.pop().unwrap(), axes.pop().unwrap());
+        x.invert = flags[0] & 1 != 0;
+        y.invert = flags[0] & 2 != 0;
+        let scale = Scale { x, y, units: Units { length, potential } };
+
+        discretization.stencil_kind.check(&scale, discretization.geometry)
+            .map_err(|message| invalid_data(format!("{}: {}", path, message)))?;
+        if let Some(ref conduction) = discretization.conduction {
+            conduction.check(discretization.geometry, discretization.stencil_kind)
+                .map_err(|message| invalid_data(format!("{}: {}", path, message)))?;
+        }
+        let width = reader.u64()? as usize;
+        let height = reader.u64()? as usize;
+        let node_count = width.checked_mul(height)
+            .filter(|&n| n > 0 && n.saturating_mul(9) <= content.len())
+            .ok_or_else(|| invalid_data(format!("{} is truncated", path)))?;
+        for &(axis, nodes) in [(&scale.x, width), (&scale.y, height)].iter() {
+            if axis.graded.as_ref().map_or(false, |positions| positions.len() != nodes) {
+                return Err(invalid_data(format!(
+                    "{} has a different number of node positions than nodes", path)));
+            }
+        }
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            nodes.push(reader.f64()?);
+        }
+        let fixed: Vec<bool> = reader.take(node_count)?.iter().map(|&b| b != 0).collect();
+        if reader.take(1)?[0] != 0 {
+            for _ in 0..node_count {
+                discretization.source.push(reader.f64()?);
+            }
+        }
+
+        let iteration = reader.u64()? as usize;
+        let over_relaxation = reader.f64()?;
+        let residual_count = reader.count(8)?;
+        let mut residuals = Vec::with_capacity(residual_count);
+        for _ in 0..residual_count {
+            residuals.push(reader.f64()?);
+        }
+
+        let mut grid = Grid::from_parts_with(nodes, width, &fixed, scale, discretization);
+        if let Some(open) = open {
+            let invalid = |message| invalid_data(format!("{}: {}", path, message));
+            // every fixed node besides the border is an electrode
+            let border = open.border(&grid.scale, grid.geometry(), width, height, &[])
+                .map_err(invalid)?.border;
+            let mut electrode = fixed;
+            for &i in border.iter() {
+                electrode[i] = false;
+            }
+            let electrode_nodes: Vec<usize> = (0..node_count).filter(|&i| electrode[i]).collect();
+            grid.open = Some(open.border(&grid.scale, grid.geometry(), width, height,
+                                         &electrode_nodes).map_err(invalid)?);
+        }
+        Ok(Checkpoint { grid, state: SolverState { iteration, over_relaxation, residuals } })
+    }
+}
+
+impl Grid {
+    /// Writes the grid and the solver state to a checkpoint at `path`. The file is written next
+    /// to it first and then renamed, so an existing checkpoint is never left half written
+    pub fn save_checkpoint(&self, path: &str, state: &SolverState) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(self.nodes.len() * 9 + state.residuals.len() * 8 + 80);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+        for axis in [&self.scale.x, &self.scale.y].iter() {
+            bytes.extend_from_slice(&axis.origin.to_le_bytes());
+            bytes.extend_from_slice(&axis.spacing.to_le_bytes());
+            let positions = axis.graded.as_ref().map_or(&[][..], |positions| &positions[..]);
+            bytes.extend_from_slice(&(positions.len() as u64).to_le_bytes());
+            for position in positions.iter() {
+                bytes.extend_from_slice(&position.to_le_bytes());
+            }
+        }
+        bytes.push(self.scale.x.invert as u8 | (self.scale.y.invert as u8) << 1);
+        bytes.push(match self.scale.units.length {
+            LengthUnit::Micrometer => 0,
+            LengthUnit::Millimeter => 1,
+            LengthUnit::Meter => 2,
+        });
+        bytes.push(match self.scale.units.potential {
+            PotentialUnit::Volt => 0,
+            PotentialUnit::Kilovolt => 1,
+        });
+        bytes.push(match self.geometry() {
+            Geometry::Planar => 0,
+            Geometry::Axisymmetric => 1,
+        });
+        bytes.push(match self.stencil_kind() {
+            StencilKind::FivePoint => 0,
+            StencilKind::NinePoint => 1,
+        });
+        bytes.extend_from_slice(&(self.conductors().len() as u64).to_le_bytes());
+        for conductor in self.conductors().iter() {
+            bytes.extend_from_slice(&conductor.potential.to_le_bytes());
+            match conductor.shape {
+                Shape::Circle { x, y, radius } => {
+                    bytes.push(0);
+                    for value in [x, y, radius].iter() {
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+                Shape::Polygon(ref corners) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&(corners.len() as u64).to_le_bytes());
+                    for &(x, y) in corners.iter() {
+                        bytes.extend_from_slice(&x.to_le_bytes());
+                        bytes.extend_from_slice(&y.to_le_bytes());
+                    }
+                }
+            }
+        }
+        match self.conduction() {
+            None => bytes.push(0),
+            Some(conduction) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&conduction.background.to_le_bytes());
+                bytes.push(conduction.insulating_border as u8);
+                bytes.extend_from_slice(&(conduction.regions.len() as u64).to_le_bytes());
+                for region in conduction.regions.iter() {
+                    bytes.extend_from_slice(&(region.x as i64).to_le_bytes());
+                    bytes.extend_from_slice(&(region.y as i64).to_le_bytes());
+                    bytes.extend_from_slice(&(region.width as u64).to_le_bytes());
+                    bytes.extend_from_slice(&(region.height as u64).to_le_bytes());
+                    bytes.extend_from_slice(&region.conductivity.to_le_bytes());
+                }
+            }
+        }
+        match self.open_boundary() {
+            None => bytes.push(0),
+            Some(open) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&open.center.0.to_le_bytes());
+                bytes.extend_from_slice(&open.center.1.to_le_bytes());
+                bytes.extend_from_slice(&(open.order as u64).to_le_bytes());
+                bytes.extend_from_slice(&(open.inset as u64).to_le_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&(self.width as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.height() as u64).to_le_bytes());
+        for node in self.nodes.iter() {
+            bytes.extend_from_slice(&node.to_le_bytes());
+        }
+        bytes.extend(self.fixed_mask().iter().map(|&fixed| fixed as u8));
+        match self.source() {
+            None => bytes.push(0),
+            Some(source) => {
+                bytes.push(1);
+                for value in source.iter() {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        bytes.extend_from_slice(&(state.iteration as u64).to_le_bytes());
+        bytes.extend_from_slice(&state.over_relaxation.to_le_bytes());
+        bytes.extend_from_slice(&(state.residuals.len() as u64).to_le_bytes());
+        for residual in state.residuals.iter() {
+            bytes.extend_from_slice(&residual.to_le_bytes());
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        let temporary = format!("{}.tmp", path);
+        File::create(&temporary)?.write_all(&bytes)?;
+        fs::rename(&temporary, path)
+    }
+
+    /// Does the same as `evaluate` but continues from `state` and writes a checkpoint to `path`
+    /// every `every` iterations (0 never). If `cancel` is set (e.g. from a signal handler or
+    /// another thread) the solve stops after the current iteration and a checkpoint is written.
+    /// Returns whether the solve converged (false if it was cancelled or gave up like
+    /// `evaluate`, a checkpoint is written then as well). Every checkpoint holds the residuals of
+    /// all iterations so far (8 bytes each), so for long solves `every` should not be too small.
+    /// Fails for a grid with an open border, the state can not hold the passes of `evaluate_open`
+    pub fn evaluate_with_checkpoints(&mut self, accepted_delta: f64, state: &mut SolverState,
+                                     path: &str, every: usize, cancel: &AtomicBool)
+        -> io::Result<bool> {
+        if self.open.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "grids with an open border can not be solved with checkpoints"));
+        }
+        if state.residuals.last().map_or(false, |&max_delta| max_delta <= accepted_delta) {
+            return Ok(true);
+        }
+        let label = if state.iteration > 0 {
+            format!("resumed after {}: ", state.iteration)
+        } else {
+            String::new()
+        };
+        let mut error = None;
+        let (_, converged) = iterate(accepted_delta, Some(&label), |_| {
+            if cancel.load(Ordering::SeqCst) {
+                return None;
+            }
+            let max_delta = self.sweep(state.over_relaxation);
+            state.iteration += 1;
+            state.residuals.push(max_delta);
+            if every != 0 && state.iteration % every == 0 {
+                if let Err(save_error) = self.save_checkpoint(path, state) {
+                    error = Some(save_error);
+                    return None;
+                }
+            }
+            Some(max_delta)
+        });
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if !converged {
+            self.save_checkpoint(path, state)?;
+        }
+        Ok(converged)
+    }
+}
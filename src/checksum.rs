@@ -0,0 +1,35 @@
+//! # Checksums
+//!
+//! the two checksums needed for writing png (crc32 and adler32) and zip files (crc32) without
+//! pulling in a compression library
+
+/// CRC-32 (ISO 3309, the one used by png and zip) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    update_crc32(0, data)
+}
+
+/// continues a CRC-32 over more data, `crc` is the result of the previous call
+pub fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Adler-32 checksum of `data` as used by zlib streams
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk.iter() {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,183 @@
+//! # Conductors
+//!
+//! electrodes that do not follow the nodes: circles and polygons in external coordinates. The
+//! nodes inside of a conductor are fixed to its potential. A dynamic node next to one does not
+//! see the neighbour inside at the full node spacing but at the point where the line between
+//! them crosses the boundary (Shortley–Weller), so the stencil weighs the potential of the
+//! conductor at the true distance. This removes the staircase error of rasterized curved or
+//! tilted electrodes without refining the whole grid.
+
+use super::Scale;
+
+/// shortest arm of a stencil, as a fraction of the node spacing
+const MIN_ARM: f64 = 1e-6;
+
+/// Outline of a conductor in external coordinates
+///
+/// * `Circle` - center `x`, `y` and `radius` (a cylinder seen from its end, or a torus or sphere
+///   in axisymmetric grids)
+/// * `Polygon` - corners in order (either way round), the last one connects to the first. It may
+///   be concave but must not intersect itself
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle { x: f64, y: f64, radius: f64 },
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Shape {
+    /// whether the point at the external coordinates `x` and `y` is inside of the shape, points
+    /// on a circle count as inside
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match *self {
+            Shape::Circle { x: center_x, y: center_y, radius } =>
+                (x - center_x).powi(2) + (y - center_y).powi(2) <= radius * radius,
+            Shape::Polygon(ref corners) => {
+                // even-odd rule with a ray in positive x direction
+                let mut inside = false;
+                let mut previous = corners[corners.len() - 1];
+                for &corner in corners.iter() {
+                    let ((x_a, y_a), (x_b, y_b)) = (previous, corner);
+                    if (y_a > y) != (y_b > y) &&
+                        x < x_a + (y - y_a) / (y_b - y_a) * (x_b - x_a) {
+                        inside = !inside;
+                    }
+                    previous = corner;
+                }
+                inside
+            }
+        }
+    }
+
+    /// smallest and biggest x and y of the shape
+    fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        match *self {
+            Shape::Circle { x, y, radius } =>
+                ((x - radius, x + radius), (y - radius, y + radius)),
+            Shape::Polygon(ref corners) => corners.iter().fold(
+                ((f64::INFINITY, f64::NEG_INFINITY), (f64::INFINITY, f64::NEG_INFINITY)),
+                |((min_x, max_x), (min_y, max_y)), &(x, y)|
+                    ((min_x.min(x), max_x.max(x)), (min_y.min(y), max_y.max(y)))),
+        }
+    }
+
+    /// Fraction of the way from `from` (outside) to `to` (inside) at which the line between them
+    /// first crosses the boundary, 1 if rounding errors hide the crossing
+    fn crossing(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        let (d_x, d_y) = (to.0 - from.0, to.1 - from.1);
+        let t = match *self {
+            Shape::Circle { x, y, radius } => {
+                let (p_x, p_y) = (from.0 - x, from.1 - y);
+                let a = d_x * d_x + d_y * d_y;
+                let b = 2.0 * (d_x * p_x + d_y * p_y);
+                let c = p_x * p_x + p_y * p_y - radius * radius;
+                let discriminant = b * b - 4.0 * a * c;
+                if discriminant < 0.0 { 1.0 } else { (-b - discriminant.sqrt()) / (2.0 * a) }
+            }
+            Shape::Polygon(ref corners) => {
+                let mut first: f64 = 1.0;
+                let mut previous = corners[corners.len() - 1];
+                for &corner in corners.iter() {
+                    let (e_x, e_y) = (corner.0 - previous.0, corner.1 - previous.1);
+                    let denominator = d_x * e_y - d_y * e_x;
+                    if denominator != 0.0 {
+                        let (q_x, q_y) = (previous.0 - from.0, previous.1 - from.1);
+                        let t = (q_x * e_y - q_y * e_x) / denominator;
+                        let s = (q_x * d_y - q_y * d_x) / denominator;
+                        if (0.0..=1.0).contains(&s) && t >= 0.0 {
+                            first = first.min(t);
+                        }
+                    }
+                    previous = corner;
+                }
+                first
+            }
+        };
+        t.clamp(0.0, 1.0)
+    }
+
+    /// why the shape can not be used, if it can not
+    pub(crate) fn check(&self) -> Result<(), String> {
+        match *self {
+            Shape::Circle { x, y, radius } => if !(x.is_finite() && y.is_finite() &&
+                radius > 0.0 && radius.is_finite()) {
+                return Err(String::from("a circle needs a finite center and a positive radius"));
+            },
+            Shape::Polygon(ref corners) => if corners.len() < 3 ||
+                !corners.iter().all(|&(x, y)| x.is_finite() && y.is_finite()) {
+                return Err(String::from("a polygon needs at least 3 finite corners"));
+            },
+        }
+        Ok(())
+    }
+}
+
+/// A conductor with a fixed potential that is not aligned with the nodes, see `GridBuilder::
+/// conductor`
+///
+/// # Fields
+///
+/// * `shape` - outline of the conductor
+/// * `potential` - fixed potential of the conductor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conductor {
+    pub shape: Shape,
+    pub potential: f64,
+}
+
+impl Conductor {
+    /// indices of the nodes of a grid `width` x `height` nodes big inside of the conductor
+    pub(crate) fn covered_nodes(&self, scale: &Scale, width: usize, height: usize) -> Vec<usize> {
+        let ((min_x, max_x), (min_y, max_y)) = self.shape.bounds();
+        let range = |a: f64, b: f64, count: usize| {
+            let (start, end) = (a.min(b).floor().max(0.0), a.max(b).ceil());
+            (start as usize, (end.max(-1.0) + 1.0).min(count as f64) as usize)
+        };
+        let columns = range(scale.x.to_internal(min_x), scale.x.to_internal(max_x), width);
+        let rows = range(scale.y.to_internal(min_y), scale.y.to_internal(max_y), height);
+        let mut nodes = Vec::new();
+        for row in rows.0..rows.1 {
+            for column in columns.0..columns.1 {
+                let (x, y) = scale.to_external(column as f64, row as f64);
+                if self.shape.contains(x, y) {
+                    nodes.push(row * width + column);
+                }
+            }
+        }
+        nodes
+    }
+}
+
+/// For every one of the `dynamic_nodes` of a grid `width` nodes wide the length of the arms to
+/// the left, right, top and bottom neighbour as a fraction of the node spacing, shorter than 1
+/// where a conductor is in between. `None` for nodes without a conductor among their eight
+/// neighbours. Arms never get shorter than `MIN_ARM` so nodes right on the boundary of a
+/// conductor do not divide by zero, their potential is the one of the conductor anyway
+pub(crate) fn arms(conductors: &[Conductor], scale: &Scale, dynamic_nodes: &[usize],
+                   width: usize, height: usize) -> Vec<Option<[f64; 4]>> {
+    // the conductors every node is inside of
+    let mut inside: Vec<Vec<usize>> = vec![Vec::new(); width * height];
+    for (index, conductor) in conductors.iter().enumerate() {
+        for i in conductor.covered_nodes(scale, width, height) {
+            inside[i].push(index);
+        }
+    }
+    let position = |i: usize| scale.to_external((i % width) as f64, (i / width) as f64);
+
+    dynamic_nodes.iter().map(|&i| {
+        // the axis of an axisymmetric grid has no left neighbour, the node stands in for it
+        let left = if i % width == 0 { i } else { i - 1 };
+        let corners = [left - width, i - width + 1, left + width, i + width + 1];
+        let sides = [left, i + 1, i - width, i + width];
+        if sides.iter().chain(corners.iter()).all(|&j| inside[j].is_empty()) {
+            return None;
+        }
+        let mut arms = [1.0f64; 4];
+        for (arm, &j) in arms.iter_mut().zip(sides.iter()) {
+            for &index in inside[j].iter() {
+                *arm = (*arm).min(conductors[index].shape.crossing(position(i), position(j))
+                    .max(MIN_ARM));
+            }
+        }
+        Some(arms)
+    }).collect()
+}
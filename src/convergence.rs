@@ -0,0 +1,203 @@
+//! # Grid Convergence Study
+//!
+//! estimates the discretization error by solving the same task at several resolutions and
+//! comparing the potential at probe points. The three finest resolutions are evaluated with the
+//! procedure of Celik et al. (2008, "Procedure for Estimation and Reporting of Uncertainty Due to
+//! Discretization in CFD Applications"): observed order of convergence, Richardson extrapolation
+//! and the Grid Convergence Index (GCI).
+//!
+//! The iteration error has to be a lot smaller than the discretization error for this to mean
+//! anything, so use a small `accepted_delta`.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+
+use super::{Grid, GridError};
+use builder::GridBuilder;
+use sample::{Interpolation, SampleError};
+
+/// safety factor of the GCI for studies with three or more grids
+const SAFETY_FACTOR: f64 = 1.25;
+
+/// Everything that can go wrong in `convergence_study`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConvergenceError {
+    /// the task does not describe a valid grid
+    Grid(GridError),
+    /// a probe is outside of the grid
+    Sample(SampleError),
+    /// there are less than three different resolutions, or they do not fit the values
+    InvalidResolutions(String),
+    /// the solve with `nodes_per_unit` did not converge, its values would be meaningless
+    NotConverged { nodes_per_unit: usize },
+}
+
+impl fmt::Display for ConvergenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConvergenceError::Grid(ref error) => write!(f, "{}", error),
+            ConvergenceError::Sample(ref error) => write!(f, "{}", error),
+            ConvergenceError::InvalidResolutions(ref message) =>
+                write!(f, "invalid resolutions: {}", message),
+            ConvergenceError::NotConverged { nodes_per_unit } =>
+                write!(f, "the solve with {} nodes per unit did not converge", nodes_per_unit),
+        }
+    }
+}
+
+impl Error for ConvergenceError {}
+
+impl From<GridError> for ConvergenceError {
+    fn from(error: GridError) -> ConvergenceError {
+        ConvergenceError::Grid(error)
+    }
+}
+
+impl From<SampleError> for ConvergenceError {
+    fn from(error: SampleError) -> ConvergenceError {
+        ConvergenceError::Sample(error)
+    }
+}
+
+/// Result for one probe point
+///
+/// # Fields
+///
+/// * `x` - external coordinate of the probe
+/// * `y` - external coordinate of the probe
+/// * `values` - potential at the probe for every resolution (same order as the resolutions)
+/// * `observed_order` - observed order of convergence p (NaN if the values do not converge
+///   monotonically or do not change at all)
+/// * `extrapolated` - Richardson extrapolated potential
+/// * `gci` - relative Grid Convergence Index of the finest resolution (0.01 means 1 %)
+#[derive(Debug, Clone)]
+pub struct ProbeConvergence {
+    pub x: f64,
+    pub y: f64,
+    pub values: Vec<f64>,
+    pub observed_order: f64,
+    pub extrapolated: f64,
+    pub gci: f64,
+}
+
+/// Result of `convergence_study`
+///
+/// # Fields
+///
+/// * `resolutions` - the nodes per unit that were solved, finest first
+/// * `probes` - the results for each probe point
+#[derive(Debug, Clone)]
+pub struct ConvergenceStudy {
+    pub resolutions: Vec<usize>,
+    pub probes: Vec<ProbeConvergence>,
+}
+
+impl ProbeConvergence {
+    /// Evaluates the potentials `values` at (`x`, `y`) which were solved with `resolutions`
+    /// (nodes per unit, finest first, one per value); only the three finest ones are used. Fails
+    /// for less than three resolutions or ones that are not strictly decreasing
+    pub fn new(x: f64, y: f64, resolutions: &[usize], values: Vec<f64>)
+        -> Result<ProbeConvergence, ConvergenceError> {
+        if resolutions.len() < 3 || resolutions.len() != values.len() ||
+            resolutions.windows(2).any(|pair| pair[1] >= pair[0]) || resolutions[2] == 0 {
+            return Err(ConvergenceError::InvalidResolutions(format!(
+                "{:?} are not at least three strictly decreasing resolutions for {} values",
+                resolutions, values.len())));
+        }
+        let h = [1.0 / resolutions[0] as f64, 1.0 / resolutions[1] as f64,
+            1.0 / resolutions[2] as f64];
+        let (observed_order, extrapolated, gci) =
+            richardson(h, [values[0], values[1], values[2]]);
+        Ok(ProbeConvergence { x, y, values, observed_order, extrapolated, gci })
+    }
+}
+
+/// Solves `task` (its resolution is ignored) for every entry of `resolutions` (nodes per unit, at
+/// least three different ones) using `Grid::solve_cascade` and evaluates the potential at
+/// `probes`. Fails for less than three resolutions, an invalid task, a solve that does not
+/// converge or a probe outside of the grid
+pub fn convergence_study(task: &GridBuilder, resolutions: &[usize], probes: &[(f64, f64)],
+                         accepted_delta: f64, over_relaxation: f64)
+    -> Result<ConvergenceStudy, ConvergenceError> {
+    let mut resolutions = resolutions.to_vec();
+    resolutions.sort_unstable_by(|a, b| b.cmp(a));
+    resolutions.dedup();
+    if resolutions.len() < 3 || resolutions.contains(&0) {
+        return Err(ConvergenceError::InvalidResolutions(format!(
+            "a convergence study needs at least three different resolutions, not {:?}",
+            resolutions)));
+    }
+
+    let mut values = vec![Vec::with_capacity(resolutions.len()); probes.len()];
+    for &nodes_per_unit in resolutions.iter() {
+        let grid = Grid::solve_cascade(&task.clone().resolution(nodes_per_unit),
+                                       accepted_delta, over_relaxation)
+            .map_err(|error| match error {
+                GridError::NotConverged { .. } => ConvergenceError::NotConverged { nodes_per_unit },
+                error => ConvergenceError::Grid(error),
+            })?;
+        for (probe, values) in probes.iter().zip(values.iter_mut()) {
+            values.push(grid.sample(probe.0, probe.1, Interpolation::Bicubic)?);
+        }
+    }
+
+    let probes = probes.iter().zip(values)
+        .map(|(&(x, y), values)| ProbeConvergence::new(x, y, &resolutions, values))
+        .collect::<Result<_, _>>()?;
+    Ok(ConvergenceStudy { resolutions, probes })
+}
+
+/// observed order, extrapolated value and fine grid GCI from three solutions `phi` with spacings
+/// `h` (both finest first)
+fn richardson(h: [f64; 3], phi: [f64; 3]) -> (f64, f64, f64) {
+    let (r21, r32) = (h[1] / h[0], h[2] / h[1]);
+    let (e21, e32) = (phi[1] - phi[0], phi[2] - phi[1]);
+    if e21 == 0.0 || e32 == 0.0 {
+        return (f64::NAN, phi[0], 0.0);
+    }
+    let s = (e32 / e21).signum();
+
+    // fixed point iteration for p, only needed if the refinement ratio is not constant
+    let mut p = (e32 / e21).abs().ln() / r21.ln();
+    for _ in 0..100 {
+        let q = ((r21.powf(p) - s) / (r32.powf(p) - s)).ln();
+        let next = ((e32 / e21).abs().ln() + q).abs() / r21.ln();
+        if !next.is_finite() { break; }
+        let converged = (next - p).abs() < 1e-12;
+        p = next;
+        if converged { break; }
+    }
+    if !p.is_finite() || p <= 0.0 {
+        return (f64::NAN, phi[0], f64::NAN);
+    }
+
+    let factor = r21.powf(p);
+    let extrapolated = (factor * phi[0] - phi[1]) / (factor - 1.0);
+    let relative_error = ((phi[0] - phi[1]) / phi[0]).abs();
+    (p, extrapolated, SAFETY_FACTOR * relative_error / (factor - 1.0))
+}
+
+impl ConvergenceStudy {
+    /// writes one row per probe: x, y, the potential for every resolution, observed order,
+    /// extrapolated potential and GCI; the first row names the columns
+    pub fn to_csv(&self, path: &str) -> std::io::Result<()> {
+        let mut csv = File::create(path)?;
+        let mut file_string = String::from("x,y");
+        for resolution in self.resolutions.iter() {
+            file_string.push_str(&format!(",potential_{}", resolution));
+        }
+        file_string.push_str(",observed_order,extrapolated,gci\n");
+        for probe in self.probes.iter() {
+            file_string.push_str(&format!("{},{}", probe.x, probe.y));
+            for value in probe.values.iter() {
+                file_string.push_str(&format!(",{}", value));
+            }
+            file_string.push_str(&format!(",{},{},{}\n", probe.observed_order,
+                                          probe.extrapolated, probe.gci));
+        }
+        csv.write_all(file_string.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! # CSV Import
+//!
+//! reading the csv files written by `Grid::to_csv` back in. The first row holds the x coordinates
+//! of the columns (after a filler), the first column the y coordinates of the rows, the `Scale`
+//! is inferred from them. Lines starting with `#` are comments, the units are read from the one
+//! `to_csv_with_units` writes (files without it get millimeters and volts).
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use super::{Grid, Scale};
+use units::{LengthUnit, PotentialUnit, Units};
+
+/// Everything that can go wrong while reading a csv file
+#[derive(Debug)]
+pub enum CsvError {
+    /// the file could not be read
+    Io(io::Error),
+    /// the file has no header row or no data rows
+    Empty,
+    /// a cell is not a finite number (lines and columns start at 1)
+    InvalidNumber { line: usize, column: usize, text: String },
+    /// a row has a different number of cells than the header row
+    RowLength { line: usize, expected: usize, found: usize },
+    /// the axis labels do not describe a valid `Scale`
+    InconsistentAxes(String),
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CsvError::Io(ref error) => write!(f, "could not read csv file: {}", error),
+            CsvError::Empty => write!(f, "the csv file contains no grid"),
+            CsvError::InvalidNumber { line, column, ref text } =>
+                write!(f, "line {}, column {}: '{}' is not a finite number", line, column,
+                       text),
+            CsvError::RowLength { line, expected, found } =>
+                write!(f, "line {} has {} cells but the first line has {}", line, found,
+                       expected),
+            CsvError::InconsistentAxes(ref message) =>
+                write!(f, "inconsistent axis labels: {}", message),
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CsvError::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(error: io::Error) -> CsvError {
+        CsvError::Io(error)
+    }
+}
+
+/// Reads a csv file written by `Grid::to_csv` or `Grid::to_csv_with_units`. The file contains
+/// no information on the fixed nodes, so only the border is fixed in the result
+pub fn read_csv(path: &str) -> Result<Grid, CsvError> {
+    let mut file_string = String::new();
+    File::open(path)?.read_to_string(&mut file_string)?;
+    parse_csv(&file_string)
+}
+
+fn parse_csv(file_string: &str) -> Result<Grid, CsvError> {
+    let units = file_string.lines().filter_map(|line| parse_units(line.trim()))
+        .next().unwrap_or_default();
+    let mut lines = file_string.lines().enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+    let (_, header) = lines.next().ok_or(CsvError::Empty)?;
+    let header = parse_line(header, 1)?;
+    if header.len() < 3 {
+        return Err(CsvError::Empty);
+    }
+    // the first cell is a filler
+    let x = header[1..].to_vec();
+
+    let mut y = Vec::new();
+    let mut nodes = Vec::with_capacity(x.len() * x.len());
+    for (i, line) in lines {
+        let row = parse_line(line, i + 1)?;
+        if row.len() != header.len() {
+            return Err(CsvError::RowLength { line: i + 1, expected: header.len(),
+                found: row.len() });
+        }
+        y.push(row[0]);
+        nodes.extend_from_slice(&row[1..]);
+    }
+    if y.len() < 2 {
+        return Err(CsvError::Empty);
+    }
+
+    let mut scale = Scale::from_axes(&x, &y).map_err(CsvError::InconsistentAxes)?;
+    scale.units = units;
+    // `from_parts` fixes the border on its own
+    let fixed = vec![false; nodes.len()];
+    Ok(Grid::from_parts(nodes, x.len(), &fixed, scale))
+}
+
+/// the units of a `# length unit <symbol>, potential unit <symbol>` comment
+fn parse_units(line: &str) -> Option<Units> {
+    let mut parts = line.strip_prefix('#')?.split(',');
+    let length = parts.next()?.trim().strip_prefix("length unit")?.trim();
+    let potential = parts.next()?.trim().strip_prefix("potential unit")?.trim();
+    Some(Units { length: LengthUnit::from_symbol(length)?,
+        potential: PotentialUnit::from_symbol(potential)? })
+}
+
+/// the numbers of one line, `line` is only used for errors. NaN and infinities are rejected,
+/// neither a potential nor a coordinate can be one
+fn parse_line(line: &str, line_number: usize) -> Result<Vec<f64>, CsvError> {
+    line.split(',').enumerate().map(|(column, cell)| {
+        cell.trim().parse::<f64>().ok().filter(|value| value.is_finite())
+            .ok_or_else(|| CsvError::InvalidNumber {
+                line: line_number, column: column + 1, text: cell.trim().to_string() })
+    }).collect()
+}
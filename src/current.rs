@@ -0,0 +1,274 @@
+//! # Current Flow
+//!
+//! steady direct current in resistive sheets: `∇·(σ ∇φ) = 0` with the conductivity σ taken from
+//! rectangular regions. The conductivity belongs to the cells between the nodes, every node
+//! balances the currents through the edges to its four neighbours (finite volumes around the
+//! nodes), and the conductance of an edge is the mean conductivity of the two cells next to it
+//! times their width across the edge over the length of the edge. Cells outside of the grid or
+//! with a conductivity of 0 do not conduct, so an insulating border is an edge with no current
+//! through it (Neumann) exactly at the outermost nodes, and holes and cutouts work the same way.
+//!
+//! Terminals are fixed boxes. The current out of a terminal is the sum over the edges from its
+//! nodes to the nodes outside of it, which balances with the currents of the other terminals (and
+//! of a fixed border) once the potential has converged. The potential of a terminal is the one
+//! its nodes are fixed to in the grid. Conductivities are sheet conductances (1 / sheet
+//! resistance, siemens per square), so resistances are in ohms if the potential is in volts and
+//! do not depend on the length unit. The resistance of a uniform sheet is its sheet resistance
+//! times the number of squares of the layout, which `squares` gives.
+
+use std::ops::Range;
+
+use super::{box_extent, FixedBox, Grid, GridError, Scale};
+use stencil::{Geometry, Stencil, StencilKind};
+
+/// A rectangle of a resistive sheet with its own conductivity, in the same coordinates as a
+/// `FixedBox`. The cells whose centers are inside of it take its conductivity
+///
+/// # Fields
+///
+/// * `x` - coordinate of the top left corner of the box
+/// * `y` - coordinate of the top left corner of the box
+/// * `width` - width of the box
+/// * `height` - height of the box
+/// * `conductivity` - sheet conductance of the box, 0 for a cutout
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConductivityBox {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+    pub conductivity: f64,
+}
+
+/// How current flows through a grid, see `GridBuilder::conduction`
+///
+/// # Fields
+///
+/// * `background` - sheet conductance of the cells outside of every region
+/// * `regions` - regions with their own conductivity, later ones cover earlier ones
+/// * `insulating_border` - whether no current leaves the sheet through the border, otherwise the
+///   border is fixed to the boundary condition like in every other grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conduction {
+    pub background: f64,
+    pub regions: Vec<ConductivityBox>,
+    pub insulating_border: bool,
+}
+
+impl Default for Conduction {
+    /// a uniform sheet with a conductivity of 1 and a fixed border, what every grid solves
+    fn default() -> Conduction {
+        Conduction { background: 1.0, regions: Vec::new(), insulating_border: false }
+    }
+}
+
+impl Conduction {
+    /// why the conduction can not be used with `geometry` and `stencil_kind`, if it can not
+    pub(crate) fn check(&self, geometry: Geometry, stencil_kind: StencilKind)
+        -> Result<(), String> {
+        if geometry != Geometry::Planar || stencil_kind != StencilKind::FivePoint {
+            return Err(String::from("current flow needs a planar grid and the 5-point stencil"));
+        }
+        let valid = |conductivity: f64| conductivity >= 0.0 && conductivity.is_finite();
+        if !valid(self.background) ||
+            !self.regions.iter().all(|region| valid(region.conductivity)) {
+            return Err(String::from("conductivities have to be finite and not negative"));
+        }
+        Ok(())
+    }
+}
+
+/// The conductances of the edges between the nodes of a grid
+pub(crate) struct Conductances {
+    width: usize,
+    height: usize,
+    /// conductivity of every cell, `width - 1` cells per row
+    cells: Vec<f64>,
+    /// distance from every column (row) to the next one
+    x_spacing: Vec<f64>,
+    y_spacing: Vec<f64>,
+}
+
+impl Conductances {
+    /// the conductances of a grid `width` x `height` nodes big
+    pub fn new(conduction: &Conduction, scale: &Scale, width: usize, height: usize)
+        -> Conductances {
+        let columns = width.saturating_sub(1);
+        let rows = height.saturating_sub(1);
+        let mut cells = vec![conduction.background; columns * rows];
+        for region in conduction.regions.iter() {
+            let (x, y) = (region.x as f64, region.y as f64);
+            let x_end = if scale.x.invert { x - region.width as f64 }
+                else { x + region.width as f64 };
+            let y_end = if scale.y.invert { y - region.height as f64 }
+                else { y + region.height as f64 };
+            let inside = |a: f64, start: f64, end: f64| a >= start.min(end) && a <= start.max(end);
+            for row in 0..rows {
+                for column in 0..columns {
+                    let (c_x, c_y) = scale.to_external(column as f64 + 0.5, row as f64 + 0.5);
+                    if inside(c_x, x, x_end) && inside(c_y, y, y_end) {
+                        cells[row * columns + column] = region.conductivity;
+                    }
+                }
+            }
+        }
+        Conductances {
+            width,
+            height,
+            cells,
+            x_spacing: (0..columns).map(|c| scale.x.node_spacing(c)).collect(),
+            y_spacing: (0..rows).map(|r| scale.y.node_spacing(r)).collect(),
+        }
+    }
+
+    /// conductivity times width across the edge of the cell in `column` and `row` for an edge
+    /// along `along_x`, 0 outside of the grid
+    fn half_cell(&self, column: isize, row: isize, along_x: bool) -> f64 {
+        let (columns, rows) = (self.width as isize - 1, self.height as isize - 1);
+        if column < 0 || row < 0 || column >= columns || row >= rows {
+            return 0.0;
+        }
+        let (column, row) = (column as usize, row as usize);
+        let across = if along_x { self.y_spacing[row] } else { self.x_spacing[column] };
+        self.cells[row * columns as usize + column] * across / 2.0
+    }
+
+    /// conductance between the node in `column` and `row` and its right neighbour
+    fn right(&self, column: usize, row: usize) -> f64 {
+        if column + 1 >= self.width {
+            return 0.0;
+        }
+        let (c, r) = (column as isize, row as isize);
+        (self.half_cell(c, r - 1, true) + self.half_cell(c, r, true)) / self.x_spacing[column]
+    }
+
+    /// conductance between the node in `column` and `row` and its neighbour below
+    fn below(&self, column: usize, row: usize) -> f64 {
+        if row + 1 >= self.height {
+            return 0.0;
+        }
+        let (c, r) = (column as isize, row as isize);
+        (self.half_cell(c - 1, r, false) + self.half_cell(c, r, false)) / self.y_spacing[row]
+    }
+
+    /// conductances to the left, right, top and bottom neighbour of node `i`
+    fn around(&self, i: usize) -> [f64; 4] {
+        let (column, row) = (i % self.width, i / self.width);
+        [if column > 0 { self.right(column - 1, row) } else { 0.0 }, self.right(column, row),
+         if row > 0 { self.below(column, row - 1) } else { 0.0 }, self.below(column, row)]
+    }
+
+    /// The stencil of node `i`, the weights are the conductances and `diagonal` is their sum
+    /// over the area of the node. Nodes without a conducting cell around them get a diagonal
+    /// of 0
+    pub fn stencil(&self, i: usize) -> Stencil {
+        let [left, right, top, bottom] = self.around(i);
+        let sum = left + right + top + bottom;
+        if sum == 0.0 {
+            return Stencil { left, right, top, bottom, corners: 0.0, diagonal: 0.0,
+                             source: 0.0 };
+        }
+        let (column, row) = (i % self.width, i / self.width);
+        let spacing = |spacings: &[f64], index: usize| {
+            let before = if index > 0 { spacings[index - 1] } else { 0.0 };
+            before + spacings.get(index).copied().unwrap_or(0.0)
+        };
+        let area = spacing(&self.x_spacing, column) * spacing(&self.y_spacing, row) / 4.0;
+        Stencil { left: left / sum, right: right / sum, top: top / sum, bottom: bottom / sum,
+                  corners: 0.0, diagonal: sum / area, source: 0.0 }
+    }
+}
+
+impl Grid {
+    /// Current flowing out of `terminal` into the sheet (the rest of the grid), from the
+    /// conductivities of the grid; grids without conduction conduct with a conductivity of 1.
+    /// Fails if the terminal is not completely inside of the grid, with `FloatingTerminal` if
+    /// its nodes are not fixed to one potential and with `InvalidConduction` if the grid is not
+    /// planar with the 5-point stencil and without conductors
+    pub fn terminal_current(&self, terminal: &FixedBox) -> Result<f64, GridError> {
+        let (columns, rows) = self.terminal_extent(terminal)?;
+        let default = Conduction::default();
+        let conduction = self.discretization.conduction.as_ref().unwrap_or(&default);
+        conduction.check(self.geometry(), self.stencil_kind())
+            .map_err(GridError::InvalidConduction)?;
+        if !self.conductors().is_empty() {
+            return Err(GridError::InvalidConduction(String::from(
+                "currents can not be computed with conductors")));
+        }
+        let width = self.width;
+        let conductances = Conductances::new(conduction, &self.scale, width, self.height());
+        let inside = |column: usize, row: usize| columns.contains(&column) && rows.contains(&row);
+
+        let mut current = 0.0;
+        for row in rows.clone() {
+            for column in columns.clone() {
+                let i = row * width + column;
+                let neighbours = [(column.wrapping_sub(1), row), (column + 1, row),
+                                  (column, row.wrapping_sub(1)), (column, row + 1)];
+                for (&conductance, &(c, r)) in conductances.around(i).iter()
+                    .zip(neighbours.iter()) {
+                    if conductance > 0.0 && !inside(c, r) {
+                        current += conductance * (self.nodes[i] - self.nodes[r * width + c]);
+                    }
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    /// Resistance between the terminals `from` and `to`, the difference of the potentials their
+    /// nodes are fixed to over the mean of the current out of `from` and into `to`. Only
+    /// meaningful if no other terminal or fixed border takes current. Fails for the same reasons
+    /// as `terminal_current` and with `NoCurrent` if no current flows
+    pub fn resistance(&self, from: &FixedBox, to: &FixedBox) -> Result<f64, GridError> {
+        let current = (self.terminal_current(from)? - self.terminal_current(to)?) / 2.0;
+        if current == 0.0 {
+            return Err(GridError::NoCurrent);
+        }
+        Ok((self.terminal_potential(from)? - self.terminal_potential(to)?) / current)
+    }
+
+    /// Number of squares between the terminals `from` and `to`, the resistance of the same
+    /// layout with a sheet resistance of 1. A device with this layout has a sheet resistance of
+    /// its measured resistance over the squares. Needs a uniform sheet, every region has to be a
+    /// cutout or conduct like the background. Fails for the same reasons as `resistance` and
+    /// with `InvalidConduction` for other regions
+    pub fn squares(&self, from: &FixedBox, to: &FixedBox) -> Result<f64, GridError> {
+        let background = self.conduction().map_or(1.0, |conduction| conduction.background);
+        if self.conduction().map_or(false, |conduction| conduction.regions.iter()
+            .any(|region| region.conductivity != 0.0 && region.conductivity != background)) {
+            return Err(GridError::InvalidConduction(String::from(
+                "squares need a uniform sheet, regions may only be cutouts")));
+        }
+        Ok(self.resistance(from, to)? * background)
+    }
+
+    /// the columns and rows of the nodes of `terminal`, they have to be inside of the grid and
+    /// fixed to one potential
+    fn terminal_extent(&self, terminal: &FixedBox)
+        -> Result<(Range<usize>, Range<usize>), GridError> {
+        let ((c_0, c_1), (r_0, r_1)) = box_extent(terminal, &self.scale);
+        if c_0 < 0 || r_0 < 0 || c_1 > self.width as isize || r_1 > self.height() as isize {
+            return Err(GridError::TerminalOutOfBounds(terminal.clone()));
+        }
+        let (columns, rows) = (c_0 as usize..c_1 as usize, r_0 as usize..r_1 as usize);
+        let fixed = self.fixed_mask();
+        let mut indices = rows.clone()
+            .flat_map(|row| columns.clone().map(move |column| row * self.width + column));
+        let floating = match indices.next() {
+            Some(first) => !fixed[first] ||
+                indices.any(|i| !fixed[i] || self.nodes[i] != self.nodes[first]),
+            None => true,
+        };
+        if floating {
+            return Err(GridError::FloatingTerminal(terminal.clone()));
+        }
+        Ok((columns, rows))
+    }
+
+    /// the potential the nodes of `terminal` are fixed to
+    fn terminal_potential(&self, terminal: &FixedBox) -> Result<f64, GridError> {
+        let (columns, rows) = self.terminal_extent(terminal)?;
+        Ok(self.nodes[rows.start * self.width + columns.start])
+    }
+}
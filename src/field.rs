@@ -0,0 +1,56 @@
+//! # Electric Field
+//!
+//! the electric field is the negative gradient of the potential, derived from the nodes with
+//! finite differences and given in external coordinates (so it respects inverted axes)
+
+use super::Grid;
+use sample::{Interpolation, SampleError};
+
+impl Grid {
+    /// Returns the electric field `(E_x, E_y)` at every node in the same order as `nodes`.
+    /// Central differences are used inside the grid and one sided ones on the border, with the
+    /// true distances between the nodes on graded axes
+    pub fn electric_field(&self) -> Vec<(f64, f64)> {
+        let height = self.height();
+        let xs: Vec<f64> = (0..self.width).map(|c| self.scale.x.to_external(c as f64)).collect();
+        let ys: Vec<f64> = (0..height).map(|r| self.scale.y.to_external(r as f64)).collect();
+        let mut field = Vec::with_capacity(self.nodes.len());
+        for row in 0..height {
+            for column in 0..self.width {
+                let (left, right) = (column.saturating_sub(1), (column + 1).min(self.width - 1));
+                let (top, bottom) = (row.saturating_sub(1), (row + 1).min(height - 1));
+                let d_x = if right > left {
+                    (self.nodes[row * self.width + right] - self.nodes[row * self.width + left])
+                        / (xs[right] - xs[left])
+                } else { 0.0 };
+                let d_y = if bottom > top {
+                    (self.nodes[bottom * self.width + column]
+                        - self.nodes[top * self.width + column]) / (ys[bottom] - ys[top])
+                } else { 0.0 };
+                field.push((-d_x, -d_y));
+            }
+        }
+        field
+    }
+
+    /// Returns the electric field `(E_x, E_y)` at the external coordinates `x` and `y`. The
+    /// gradient is taken from the interpolated potential half a node spacing to either side
+    pub fn sample_field(&self, x: f64, y: f64, interpolation: Interpolation)
+        -> Result<(f64, f64), SampleError> {
+        let (column, row) = self.internal_position(x, y)?;
+        let (max_column, max_row) = ((self.width - 1) as f64, (self.height() - 1) as f64);
+        let (left, right) = ((column - 0.5).max(0.0), (column + 0.5).min(max_column));
+        let (top, bottom) = ((row - 0.5).max(0.0), (row + 0.5).min(max_row));
+        let d_x = if right > left {
+            (self.sample_internal(right, row, interpolation)
+                - self.sample_internal(left, row, interpolation))
+                / (self.scale.x.to_external(right) - self.scale.x.to_external(left))
+        } else { 0.0 };
+        let d_y = if bottom > top {
+            (self.sample_internal(column, bottom, interpolation)
+                - self.sample_internal(column, top, interpolation))
+                / (self.scale.y.to_external(bottom) - self.scale.y.to_external(top))
+        } else { 0.0 };
+        Ok((-d_x, -d_y))
+    }
+}
@@ -0,0 +1,118 @@
+//! # Graded Axes
+//!
+//! most of a domain is usually empty space where the potential is smooth, fine detail is only
+//! needed around the edges of the electrodes. A graded axis puts its nodes close together at a
+//! few chosen lines and lets the spacing grow geometrically away from them.
+
+use super::Axis;
+
+/// most cells of one graded axis, every cell is at least `min_spacing` long so this bounds the
+/// loop that places them
+const MAX_CELLS: f64 = 1e7;
+
+/// Geometric grading of one axis, see `GridBuilder::grade`
+///
+/// # Fields
+///
+/// * `lines` - external coordinates where the spacing is `min_spacing`, there always is a node
+///   on them (e.g. the edges of the electrodes)
+/// * `min_spacing` - spacing at the lines
+/// * `max_spacing` - the spacing never grows beyond this
+/// * `ratio` - factor between the spacings of neighbouring cells, at least 1 (1.1 to 1.3 keep
+///   the error of the stencil small)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grading {
+    pub lines: Vec<f64>,
+    pub min_spacing: f64,
+    pub max_spacing: f64,
+    pub ratio: f64,
+}
+
+impl Grading {
+    pub fn new(lines: &[f64], min_spacing: f64, max_spacing: f64, ratio: f64) -> Grading {
+        Grading { lines: lines.to_vec(), min_spacing, max_spacing, ratio }
+    }
+
+    /// Distances of the nodes from the origin of `axis` for an axis `length` units long, the
+    /// first node is at 0 and the last one at `length`. Fails if the parameters are invalid, a
+    /// line is outside of the axis or the axis could get more than `MAX_CELLS` cells
+    pub(crate) fn positions(&self, axis: &Axis, length: f64, name: &str)
+        -> Result<Vec<f64>, String> {
+        let valid = self.min_spacing > 0.0 && self.max_spacing >= self.min_spacing &&
+            self.max_spacing.is_finite() && self.ratio >= 1.0 && self.ratio.is_finite();
+        if !valid {
+            return Err(format!("the grading of the {} axis needs 0 < min spacing <= max spacing \
+                                and a ratio of at least 1", name));
+        }
+        if length / self.min_spacing > MAX_CELLS {
+            return Err(format!("a min spacing of {} gives the {} axis too many nodes",
+                               self.min_spacing, name));
+        }
+        let tolerance = self.min_spacing * 1e-6;
+        let mut lines = Vec::with_capacity(self.lines.len());
+        for &line in self.lines.iter() {
+            let distance = if axis.invert { axis.origin - line } else { line - axis.origin };
+            if !(distance >= -tolerance && distance <= length + tolerance) {
+                return Err(format!("the line at {} is outside of the {} axis", line, name));
+            }
+            lines.push(distance.max(0.0).min(length));
+        }
+        lines.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        // segments between the ends of the axis and the lines, fine at the ends that are lines
+        let mut breaks = vec![(0.0, false)];
+        for &line in lines.iter() {
+            if line - breaks[breaks.len() - 1].0 <= tolerance {
+                let last = breaks.len() - 1;
+                breaks[last].1 = true;
+            } else {
+                breaks.push((line, true));
+            }
+        }
+        if length - breaks[breaks.len() - 1].0 > tolerance {
+            breaks.push((length, false));
+        } else {
+            let last = breaks.len() - 1;
+            breaks[last].0 = length;
+        }
+
+        let mut positions = vec![0.0];
+        for segment in breaks.windows(2) {
+            let ((start, fine_start), (end, fine_end)) = (segment[0], segment[1]);
+            let mut position = start;
+            for cell in self.cells(end - start, fine_start, fine_end) {
+                position += cell;
+                positions.push(position);
+            }
+            let last = positions.len() - 1;
+            positions[last] = end;
+        }
+        Ok(positions)
+    }
+
+    /// sizes of the cells of a segment `length` units long. Cells are taken alternately from the
+    /// fine ends as long as they fit, the rest is spread over all of them
+    fn cells(&self, length: f64, fine_start: bool, fine_end: bool) -> Vec<f64> {
+        let size = |count: usize, fine: bool| if fine {
+            (self.min_spacing * self.ratio.powi(count.min(i32::MAX as usize) as i32))
+                .min(self.max_spacing)
+        } else { self.max_spacing };
+
+        let (mut from_start, mut from_end) = (Vec::new(), Vec::new());
+        let mut sum = 0.0;
+        loop {
+            let (a, b) = (size(from_start.len(), fine_start), size(from_end.len(), fine_end));
+            let cell = a.min(b);
+            if sum + cell > length { break; }
+            sum += cell;
+            if a <= b { from_start.push(cell); } else { from_end.push(cell); }
+        }
+        if from_start.is_empty() && from_end.is_empty() {
+            return vec![length];
+        }
+        from_end.reverse();
+        from_start.extend(from_end);
+        let stretch = length / sum;
+        from_start.iter().map(|cell| cell * stretch).collect()
+    }
+}
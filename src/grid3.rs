@@ -0,0 +1,423 @@
+//! # Three Dimensional Grids
+//!
+//! `Grid3` is the counterpart of `Grid` for geometries that are not uniform along any direction.
+//! The nodes are stored as layers of rows, the 7-point stencil replaces every dynamic node with
+//! the mean of its six neighbours (weighted with the inverse squared spacing if the axes are
+//! spaced differently). All axes are evenly spaced and the border is fixed at 0. Slices through
+//! the volume are ordinary `Grid`s, so they can be written as csv or plotted like any other grid.
+
+use rand::prelude::*;
+use rand::ChaChaRng;
+
+use super::{iterate, Axis, Grid, GridError, Scale};
+use thread_pool::{ThreadPool, WeightedMean};
+use units::{LengthUnit, PotentialUnit, Units};
+
+/// Coordinate system of a `Grid3`, a `Scale` with a third axis
+///
+/// # Fields
+///
+/// * `x` - the x axis (columns)
+/// * `y` - the y axis (rows)
+/// * `z` - the z axis (layers)
+/// * `units` - units of the coordinates and the potential
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale3 {
+    pub x: Axis,
+    pub y: Axis,
+    pub z: Axis,
+    pub units: Units,
+}
+
+impl Scale3 {
+    /// the same `spacing` on all axes, the origin at 0, no inverted axes, millimeters and volts
+    pub fn uniform(spacing: f64) -> Scale3 {
+        let axis = Axis { origin: 0.0, spacing, invert: false, graded: None };
+        Scale3 { x: axis.clone(), y: axis.clone(), z: axis, units: Units::default() }
+    }
+
+    /// Translates external coordinates to internal ones (column, row and layer), not rounded
+    pub fn to_internal(&self, x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+        (self.x.to_internal(x), self.y.to_internal(y), self.z.to_internal(z))
+    }
+
+    /// The inverse of `to_internal`
+    pub fn to_external(&self, column: f64, row: f64, layer: f64) -> (f64, f64, f64) {
+        (self.x.to_external(column), self.y.to_external(row), self.z.to_external(layer))
+    }
+}
+
+/// A box with a fixed potential in a `Grid3`, the counterpart of `FixedBox`
+///
+/// # Fields
+///
+/// * `x`, `y`, `z` - coordinates of the corner the box extends from
+/// * `width`, `height`, `depth` - size of the box along x, y and z
+/// * `potential` - fixed potential across the box
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedBox3 {
+    pub x: isize,
+    pub y: isize,
+    pub z: isize,
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub potential: f64,
+}
+
+/// Collects the description of a three dimensional task, `build` turns it into a `Grid3` like
+/// `GridBuilder` does for a `Grid`. The builder is not consumed by `build`
+#[derive(Debug, Clone)]
+pub struct Grid3Builder {
+    width: usize,
+    height: usize,
+    depth: usize,
+    scale: Scale3,
+    electrodes: Vec<FixedBox3>,
+    seed: Option<[u8; 32]>,
+}
+
+impl Default for Grid3Builder {
+    fn default() -> Grid3Builder {
+        Grid3Builder::new()
+    }
+}
+
+impl Grid3Builder {
+    pub fn new() -> Grid3Builder {
+        Grid3Builder { width: 0, height: 0, depth: 0, scale: Scale3::uniform(1.0),
+                       electrodes: Vec::new(), seed: None }
+    }
+
+    /// size of the grid in units
+    pub fn size(mut self, width: usize, height: usize, depth: usize) -> Grid3Builder {
+        self.width = width;
+        self.height = height;
+        self.depth = depth;
+        self
+    }
+
+    /// the same number of nodes per unit in all directions
+    pub fn resolution(self, nodes_per_unit: usize) -> Grid3Builder {
+        let spacing = 1.0 / nodes_per_unit as f64;
+        self.spacing(spacing, spacing, spacing)
+    }
+
+    /// distance between neighbouring nodes in x, y and z direction
+    pub fn spacing(mut self, dx: f64, dy: f64, dz: f64) -> Grid3Builder {
+        self.scale.x.spacing = dx;
+        self.scale.y.spacing = dy;
+        self.scale.z.spacing = dz;
+        self
+    }
+
+    /// external coordinates of the first node (column, row and layer 0)
+    pub fn origin(mut self, x: f64, y: f64, z: f64) -> Grid3Builder {
+        self.scale.x.origin = x;
+        self.scale.y.origin = y;
+        self.scale.z.origin = z;
+        self
+    }
+
+    /// whether the coordinates decrease with the column, row and layer
+    pub fn invert(mut self, invert_x: bool, invert_y: bool, invert_z: bool) -> Grid3Builder {
+        self.scale.x.invert = invert_x;
+        self.scale.y.invert = invert_y;
+        self.scale.z.invert = invert_z;
+        self
+    }
+
+    /// units of the coordinates and the potential
+    pub fn units(mut self, length: LengthUnit, potential: PotentialUnit) -> Grid3Builder {
+        self.scale.units = Units { length, potential };
+        self
+    }
+
+    /// sets spacing, origin, axis inversion and units at once
+    pub fn scale(mut self, scale: Scale3) -> Grid3Builder {
+        self.scale = scale;
+        self
+    }
+
+    /// adds one fixed box
+    pub fn electrode(mut self, electrode: FixedBox3) -> Grid3Builder {
+        self.electrodes.push(electrode);
+        self
+    }
+
+    /// adds several fixed boxes, errors name them by their position among all added boxes
+    pub fn electrodes(mut self, electrodes: &[FixedBox3]) -> Grid3Builder {
+        self.electrodes.extend_from_slice(electrodes);
+        self
+    }
+
+    /// seed of the random initial guess, without one every grid starts with different noise
+    pub fn seed(mut self, seed: [u8; 32]) -> Grid3Builder {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// the grid of the task, fails for the same reasons as `Grid3::new`
+    pub fn build(&self) -> Result<Grid3, GridError> {
+        Grid3::new(self.width, self.height, self.depth, &self.electrodes, &self.scale, self.seed)
+    }
+}
+
+/// Planes a `Grid3` can be sliced along
+///
+/// * `Xy` - constant z, the columns of the slice are x and the rows y
+/// * `Xz` - constant y, the columns are x and the rows z
+/// * `Yz` - constant x, the columns are y and the rows z
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Plane {
+    Xy,
+    Xz,
+    Yz,
+}
+
+/// the 7-point stencil, the weights of the neighbours along each axis add up to 1/2 in total
+struct Stencil7 {
+    x: f64,
+    y: f64,
+    z: f64,
+    width: usize,
+    layer: usize,
+}
+
+impl WeightedMean for Stencil7 {
+    #[inline]
+    fn mean(&self, nodes: &[f64], _k: usize, i: usize) -> f64 {
+        (nodes[i - 1] + nodes[i + 1]) * self.x
+            + (nodes[i - self.width] + nodes[i + self.width]) * self.y
+            + (nodes[i - self.layer] + nodes[i + self.layer]) * self.z
+    }
+}
+
+/// Storing a three dimensional grid with all its nodes
+///
+/// # Fields
+///
+/// * `nodes` - the nodes, `width` nodes form a row and `height` rows a layer
+/// * `width` - number of nodes in the x direction
+/// * `height` - number of nodes in the y direction
+/// * `dynamic_nodes_indices` - the indices of the nodes that can change
+/// * `scale` - coordinate system of the grid
+/// * `stencil` - the weights of the neighbours, the same for every node
+pub struct Grid3 {
+    pub nodes: Vec<f64>,
+    pub width: usize,
+    pub height: usize,
+    dynamic_nodes_indices: Vec<usize>,
+    pub scale: Scale3,
+    stencil: Stencil7,
+}
+
+/// first and one past the last index covered along `axis` by something that starts at `start`
+/// and is `length` units long
+fn axis_extent(axis: &Axis, start: isize, length: usize) -> (isize, isize) {
+    let (start, length) = (start as f64, length as f64);
+    let end = if axis.invert { start - length } else { start + length };
+    (axis.to_internal(start).round() as isize, axis.to_internal(end).round() as isize)
+}
+
+impl Grid3 {
+    /// Creates a grid of `width` x `height` x `depth` units with the border at 0 and random noise
+    /// (seeded with `seed` if there is one) on the dynamic nodes. Fails if the grid is empty, an
+    /// axis of the scale is graded or invalid, a fixed box is not completely inside of the grid
+    /// or two overlapping fixed boxes have different potentials. `Grid3Builder` describes the
+    /// same with named settings
+    pub fn new(width: usize, height: usize, depth: usize, fixed_elements: &[FixedBox3],
+               scale: &Scale3, seed: Option<[u8; 32]>) -> Result<Grid3, GridError> {
+        for &(axis, name) in [(&scale.x, "x"), (&scale.y, "y"), (&scale.z, "z")].iter() {
+            if axis.is_graded() {
+                return Err(GridError::InvalidScale(format!(
+                    "the {} axis of a three dimensional grid has to be evenly spaced", name)));
+            }
+            if !(axis.spacing > 0.0 && axis.spacing.is_finite() && axis.origin.is_finite()) {
+                return Err(GridError::InvalidScale(format!(
+                    "the {} axis needs a positive spacing and a finite origin", name)));
+            }
+        }
+        let columns = (width as f64 / scale.x.spacing).round();
+        let rows = (height as f64 / scale.y.spacing).round();
+        let layers = (depth as f64 / scale.z.spacing).round();
+        if columns < 1.0 || rows < 1.0 || layers < 1.0 {
+            return Err(GridError::ZeroVolume { width, height, depth });
+        }
+        if columns * rows * layers > isize::MAX as f64 {
+            return Err(GridError::InvalidScale(format!(
+                "{} x {} x {} units are too many nodes with this scale", width, height, depth)));
+        }
+        let size = (columns as usize, rows as usize, layers as usize);
+        let layer = size.0 * size.1;
+
+        // internal extent of every box along x, y and z
+        let mut extents = Vec::with_capacity(fixed_elements.len());
+        for (index, fixed_box) in fixed_elements.iter().enumerate() {
+            let extent = [axis_extent(&scale.x, fixed_box.x, fixed_box.width),
+                          axis_extent(&scale.y, fixed_box.y, fixed_box.height),
+                          axis_extent(&scale.z, fixed_box.z, fixed_box.depth)];
+            let inside = extent.iter().zip([size.0, size.1, size.2].iter())
+                .all(|(&(start, end), &nodes)| start >= 0 && end <= nodes as isize);
+            if !inside {
+                return Err(GridError::OutOfBounds3 { index, fixed_box: fixed_box.clone() });
+            }
+            extents.push(extent);
+        }
+        for (first, extent) in extents.iter().enumerate() {
+            for (second, other) in extents.iter().enumerate().skip(first + 1) {
+                if fixed_elements[first].potential == fixed_elements[second].potential {
+                    continue;
+                }
+                let corner: Vec<isize> = extent.iter().zip(other.iter())
+                    .map(|(a, b)| a.0.max(b.0)).collect();
+                let overlap = extent.iter().zip(other.iter()).zip(corner.iter())
+                    .all(|((a, b), &start)| start < a.1.min(b.1));
+                if overlap {
+                    let (x, y, z) = scale.to_external(corner[0] as f64, corner[1] as f64,
+                                                      corner[2] as f64);
+                    return Err(GridError::OverlapConflict3 { first, second, x, y, z });
+                }
+            }
+        }
+
+        let mut nodes = vec![0.0; layer * size.2];
+        let mut fixed = vec![false; nodes.len()];
+        for (i, fixed) in fixed.iter_mut().enumerate() {
+            let (column, row, layer_index) = (i % size.0, i / size.0 % size.1, i / layer);
+            *fixed = column == 0 || row == 0 || layer_index == 0 || column == size.0 - 1 ||
+                row == size.1 - 1 || layer_index == size.2 - 1;
+        }
+        for (fixed_box, extent) in fixed_elements.iter().zip(extents.iter()) {
+            for layer_index in extent[2].0 as usize..extent[2].1 as usize {
+                for row in extent[1].0 as usize..extent[1].1 as usize {
+                    for column in extent[0].0 as usize..extent[0].1 as usize {
+                        let i = layer_index * layer + row * size.0 + column;
+                        fixed[i] = true;
+                        nodes[i] = fixed_box.potential;
+                    }
+                }
+            }
+        }
+        let dynamic_nodes_indices: Vec<usize> = (0..nodes.len()).filter(|&i| !fixed[i]).collect();
+
+        let mut random = match seed {
+            Some(seed) => ChaChaRng::from_seed(seed),
+            None => ChaChaRng::from_entropy(),
+        };
+        for &i in dynamic_nodes_indices.iter() {
+            nodes[i] = random.gen_range(0.0, 100.0);
+        }
+
+        let (x, y, z) = (scale.x.spacing.powi(-2), scale.y.spacing.powi(-2),
+                         scale.z.spacing.powi(-2));
+        let sum = 2.0 * (x + y + z);
+        let stencil = Stencil7 { x: x / sum, y: y / sum, z: z / sum, width: size.0, layer };
+        Ok(Grid3 { nodes, width: size.0, height: size.1, dynamic_nodes_indices,
+                   scale: scale.clone(), stencil })
+    }
+
+    /// number of nodes in the z direction
+    pub fn depth(&self) -> usize {
+        self.nodes.len() / (self.width * self.height)
+    }
+
+    /// true for every node with a fixed potential (border and fixed boxes), in the same order as
+    /// `nodes`
+    pub fn fixed_mask(&self) -> Vec<bool> {
+        let mut mask = vec![true; self.nodes.len()];
+        for &i in self.dynamic_nodes_indices.iter() {
+            mask[i] = false;
+        }
+        mask
+    }
+
+    /// one iteration over all dynamic nodes (successive over relaxation), returns the biggest
+    /// delta of this iteration
+    pub fn sweep(&mut self, over_relaxation: f64) -> f64 {
+        let mut max_delta = 0.0;
+        for (k, &i) in self.dynamic_nodes_indices.iter().enumerate() {
+            let new_value = self.stencil.mean(&self.nodes, k, i);
+            let delta = self.nodes[i] - new_value;
+            self.nodes[i] -= over_relaxation * delta;
+            if delta.abs() > max_delta { max_delta = delta.abs() };
+        }
+        max_delta
+    }
+
+    /// iterates until the max delta of an iteration is below `accepted_delta`, returns whether
+    /// it converged like `Grid::evaluate`
+    pub fn evaluate(&mut self, accepted_delta: f64, over_relaxation: f64) -> bool {
+        iterate(accepted_delta, Some(""), |_| Some(self.sweep(over_relaxation))).1
+    }
+
+    /// does the same as `evaluate` on `threads` threads, see `Grid::evaluate_multi_thread`
+    pub fn evaluate_multi_thread(&mut self, accepted_delta: f64, over_relaxation: f64,
+                                 threads: usize) -> bool {
+        let pool = ThreadPool::new(threads, &mut self.nodes, &self.dynamic_nodes_indices,
+                                   self.stencil.layer, &self.stencil, over_relaxation);
+        pool.evaluate(accepted_delta, self.dynamic_nodes_indices.len())
+    }
+
+    /// Returns the electric field `(E_x, E_y, E_z)` at every node in the same order as `nodes`,
+    /// with central differences inside the grid and one sided ones on the border
+    pub fn electric_field(&self) -> Vec<(f64, f64, f64)> {
+        let sizes = [self.width, self.height, self.depth()];
+        let strides = [1, self.width, self.stencil.layer];
+        let axes = [&self.scale.x, &self.scale.y, &self.scale.z];
+        let mut field = Vec::with_capacity(self.nodes.len());
+        for i in 0..self.nodes.len() {
+            let mut gradient = [0.0; 3];
+            for d in 0..3 {
+                let index = i / strides[d] % sizes[d];
+                let (before, after) = (index.saturating_sub(1), (index + 1).min(sizes[d] - 1));
+                if after > before {
+                    let base = i - index * strides[d];
+                    gradient[d] = (self.nodes[base + after * strides[d]]
+                        - self.nodes[base + before * strides[d]])
+                        / (axes[d].to_external(after as f64) - axes[d].to_external(before as f64));
+                }
+            }
+            field.push((-gradient[0], -gradient[1], -gradient[2]));
+        }
+        field
+    }
+
+    /// The nodes in the `plane` closest to `coordinate` (the external z coordinate for `Xy`, y
+    /// for `Xz` and x for `Yz`) as a two dimensional grid, `None` if the coordinate is outside
+    /// of the grid. The border of the slice and the nodes of fixed boxes stay fixed
+    pub fn slice(&self, plane: Plane, coordinate: f64) -> Option<Grid> {
+        let (depth, layer) = (self.depth(), self.stencil.layer);
+        let (axis, count) = match plane {
+            Plane::Xy => (&self.scale.z, depth),
+            Plane::Xz => (&self.scale.y, self.height),
+            Plane::Yz => (&self.scale.x, self.width),
+        };
+        let index = axis.to_internal(coordinate).round();
+        if !(index >= 0.0 && index < count as f64) {
+            return None;
+        }
+        let index = index as usize;
+        // axes of the slice, its size and the steps in `nodes` along its columns and rows
+        let (columns, rows, width, height, offset, steps) = match plane {
+            Plane::Xy => (&self.scale.x, &self.scale.y, self.width, self.height, index * layer,
+                          (1, self.width)),
+            Plane::Xz => (&self.scale.x, &self.scale.z, self.width, depth, index * self.width,
+                          (1, layer)),
+            Plane::Yz => (&self.scale.y, &self.scale.z, self.height, depth, index,
+                          (self.width, layer)),
+        };
+        let mask = self.fixed_mask();
+        let (mut nodes, mut fixed) = (Vec::with_capacity(width * height),
+                                      Vec::with_capacity(width * height));
+        for r in 0..height {
+            for c in 0..width {
+                let i = offset + c * steps.0 + r * steps.1;
+                nodes.push(self.nodes[i]);
+                fixed.push(mask[i]);
+            }
+        }
+        let scale = Scale { x: columns.clone(), y: rows.clone(), units: self.scale.units };
+        Some(Grid::from_parts(nodes, width, &fixed, scale))
+    }
+}
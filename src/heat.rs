@@ -0,0 +1,207 @@
+//! # Heat Conduction
+//!
+//! transient diffusion `∂u/∂t = α ∇²u` on the nodes of a grid: the potential of the nodes is the
+//! temperature, fixed boxes, conductors and the border keep their temperature and the rest of the
+//! nodes start with the initial guess of the builder. The Laplacian is the one the stencils of
+//! the grid discretize, so graded axes, axisymmetric grids, both stencils and conductors work the
+//! same way as for the Laplace equation. With conduction the diffusivity is scaled by the
+//! conductivity of the cells and insulating borders let no heat out. A source of the builder
+//! heats the nodes, `∂u/∂t = α (∇²u + f)`.
+//!
+//! Explicit (forward Euler) steps are cheap but only stable while `α Δt` times the biggest
+//! diagonal of the stencils is at most 1, which is `Δt <= h² / 4α` on square grids and a lot
+//! shorter next to conductors. Crank–Nicolson steps are stable for any time step, every step
+//! solves `(1 - α Δt/2 ∇²) u_new = (1 + α Δt/2 ∇²) u_old` with successive over relaxation.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::{iterate, Grid};
+use sample::{Interpolation, SampleError};
+use stencil::StencilKind;
+
+/// How the temperature advances by one time step
+///
+/// * `Explicit` - forward Euler, the time step has to obey the stability limit
+/// * `CrankNicolson` - implicit and second order in time, every step iterates until the max delta
+///   is below `accepted_delta`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeStepping {
+    Explicit,
+    CrankNicolson { accepted_delta: f64, over_relaxation: f64 },
+}
+
+/// Settings of a transient run, see `Grid::evolve`
+///
+/// # Fields
+///
+/// * `diffusivity` - thermal diffusivity α in squared length units per time unit
+/// * `time_step` - length of one step Δt
+/// * `steps` - number of steps
+/// * `stepping` - explicit or Crank–Nicolson
+/// * `snapshot_every` - the nodes are written to `<snapshot_prefix><step>.csv` (see
+///   `Grid::to_csv`) before the first step and after every `snapshot_every`th step, 0 for none
+/// * `snapshot_prefix` - path and start of the file name of the snapshots
+/// * `probes` - external coordinates at which the temperature is recorded after every step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transient {
+    pub diffusivity: f64,
+    pub time_step: f64,
+    pub steps: usize,
+    pub stepping: TimeStepping,
+    pub snapshot_every: usize,
+    pub snapshot_prefix: String,
+    pub probes: Vec<(f64, f64)>,
+}
+
+/// Everything that can go wrong in `Grid::evolve`
+#[derive(Debug)]
+pub enum TransientError {
+    /// the diffusivity or the time step is not positive and finite
+    InvalidSettings(String),
+    /// the explicit time step is longer than the stability limit `limit`
+    Unstable { time_step: f64, limit: f64 },
+    /// a probe is outside of the grid
+    Sample(SampleError),
+    /// a snapshot could not be written
+    Io(io::Error),
+    /// the iterations of Crank–Nicolson step number `step` diverged or did not converge within
+    /// the iteration limit
+    NotConverged { step: usize },
+}
+
+impl fmt::Display for TransientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransientError::InvalidSettings(ref message) =>
+                write!(f, "invalid transient settings: {}", message),
+            TransientError::Unstable { time_step, limit } =>
+                write!(f, "the explicit time step {} is longer than the stability limit {}",
+                       time_step, limit),
+            TransientError::Sample(ref error) => write!(f, "{}", error),
+            TransientError::Io(ref error) => write!(f, "could not write a snapshot: {}", error),
+            TransientError::NotConverged { step } =>
+                write!(f, "the iterations of step {} did not converge", step),
+        }
+    }
+}
+
+impl Error for TransientError {}
+
+impl From<SampleError> for TransientError {
+    fn from(error: SampleError) -> TransientError {
+        TransientError::Sample(error)
+    }
+}
+
+impl From<io::Error> for TransientError {
+    fn from(error: io::Error) -> TransientError {
+        TransientError::Io(error)
+    }
+}
+
+impl Grid {
+    /// Longest stable time step of explicit steps with the thermal `diffusivity`, infinite for a
+    /// grid without dynamic nodes
+    pub fn stable_time_step(&self, diffusivity: f64) -> f64 {
+        let diagonal = self.stencils.iter().fold(0.0f64, |max, stencil| max.max(stencil.diagonal));
+        1.0 / (diffusivity * diagonal)
+    }
+
+    /// Advances the temperature in the nodes by `transient.steps` time steps. The temperature
+    /// at every probe is appended to `probe_data` before the first and after every step, one
+    /// series per probe ended by `f64::NEG_INFINITY` like the watch data of `evaluate`, so
+    /// `watch_data_to_csv` writes one row per probe. Fails before the first step for invalid
+    /// settings, an explicit time step above `stable_time_step` or a probe outside of the grid,
+    /// and with `NotConverged` at the first Crank–Nicolson step that does not converge
+    pub fn evolve(&mut self, transient: &Transient, probe_data: &mut Vec<f64>)
+        -> Result<(), TransientError> {
+        let (diffusivity, time_step) = (transient.diffusivity, transient.time_step);
+        if !(diffusivity > 0.0 && diffusivity.is_finite() && time_step > 0.0 &&
+            time_step.is_finite()) {
+            return Err(TransientError::InvalidSettings(String::from(
+                "the diffusivity and the time step have to be positive and finite")));
+        }
+        if transient.stepping == TimeStepping::Explicit {
+            let limit = self.stable_time_step(diffusivity);
+            if time_step > limit {
+                return Err(TransientError::Unstable { time_step, limit });
+            }
+        }
+        let mut series = Vec::with_capacity(transient.probes.len());
+        for &(x, y) in transient.probes.iter() {
+            let mut values = Vec::with_capacity(transient.steps + 1);
+            values.push(self.sample(x, y, Interpolation::Bilinear)?);
+            series.push(values);
+        }
+        if transient.snapshot_every > 0 {
+            self.to_csv(&format!("{}{}.csv", transient.snapshot_prefix, 0))?;
+        }
+
+        for step in 1..transient.steps + 1 {
+            match transient.stepping {
+                TimeStepping::Explicit => self.explicit_step(diffusivity * time_step),
+                TimeStepping::CrankNicolson { accepted_delta, over_relaxation } => {
+                    if !self.crank_nicolson_step(diffusivity * time_step, accepted_delta,
+                                                 over_relaxation) {
+                        return Err(TransientError::NotConverged { step });
+                    }
+                }
+            }
+            for (values, &(x, y)) in series.iter_mut().zip(transient.probes.iter()) {
+                values.push(self.sample(x, y, Interpolation::Bilinear)?);
+            }
+            if transient.snapshot_every > 0 && step % transient.snapshot_every == 0 {
+                self.to_csv(&format!("{}{}.csv", transient.snapshot_prefix, step))?;
+            }
+        }
+        for values in series {
+            probe_data.extend(values);
+            probe_data.push(f64::NEG_INFINITY);
+        }
+        Ok(())
+    }
+
+    /// the weighted mean of the neighbours of node `i`, the `k`th dynamic node
+    fn neighbour_mean(&self, nodes: &[f64], k: usize, i: usize) -> f64 {
+        match self.discretization.stencil_kind {
+            StencilKind::FivePoint if self.discretization.dynamic_border() =>
+                self.stencils[k].apply_border(nodes, i, self.width),
+            StencilKind::FivePoint => self.stencils[k].apply(nodes, i, self.width),
+            StencilKind::NinePoint => self.stencils[k].apply_nine(nodes, i, self.width),
+        }
+    }
+
+    /// one forward Euler step, `factor` is α Δt
+    fn explicit_step(&mut self, factor: f64) {
+        let old = self.nodes.clone();
+        for (k, &i) in self.dynamic_nodes_indices.iter().enumerate() {
+            let laplacian = self.stencils[k].diagonal * (self.neighbour_mean(&old, k, i) - old[i]);
+            self.nodes[i] = old[i] + factor * laplacian;
+        }
+    }
+
+    /// one Crank–Nicolson step, `factor` is α Δt, returns whether its iterations converged
+    fn crank_nicolson_step(&mut self, factor: f64, accepted_delta: f64, over_relaxation: f64)
+        -> bool {
+        let half = factor / 2.0;
+        let right_side: Vec<f64> = self.dynamic_nodes_indices.iter().enumerate().map(|(k, &i)| {
+            let laplacian = self.stencils[k].diagonal *
+                (self.neighbour_mean(&self.nodes, k, i) - self.nodes[i]);
+            self.nodes[i] + half * laplacian
+        }).collect();
+        iterate(accepted_delta, None, |_| {
+            let mut max_delta: f64 = 0.0;
+            for (k, &i) in self.dynamic_nodes_indices.iter().enumerate() {
+                let weight = half * self.stencils[k].diagonal;
+                let new_value = (right_side[k] + weight * self.neighbour_mean(&self.nodes, k, i)) /
+                    (1.0 + weight);
+                let delta = new_value - self.nodes[i];
+                self.nodes[i] += over_relaxation * delta;
+                max_delta = max_delta.max(delta.abs());
+            }
+            Some(max_delta)
+        }).1
+    }
+}
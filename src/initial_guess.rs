@@ -0,0 +1,126 @@
+//! # Initial Guess
+//!
+//! the values the dynamic nodes start with. Random noise (the default of `GridBuilder`) converges
+//! slowly and gives misleading transients in the watch data, the other strategies start a lot
+//! closer to the solution.
+
+use rand::prelude::*;
+use rand::ChaChaRng;
+
+use super::{iterate, Grid, GridError};
+use builder::GridBuilder;
+use sample::Interpolation;
+
+/// Strategies for filling the dynamic nodes of a new grid
+///
+/// * `Zero` - all dynamic nodes start at 0
+/// * `Constant` - all dynamic nodes start at the given value
+/// * `Random` - uniform noise in [0, 100) from `ChaChaRng` with the given seed (or a random one)
+/// * `FixedMean` - the mean of all fixed nodes (border and boxes, so bigger boxes weigh more)
+/// * `Harmonic` - the inverse distance weighted mean of the nearest fixed node to the left, to the
+///   right, above and below; a cheap approximation of the harmonic interpolation of the fixed
+///   potentials
+/// * `Coarse` - the grid is solved with only every `factor`th node first (until the max delta is
+///   below `accepted_delta`) and that solution is interpolated to the full resolution
+/// * `Existing` - the potential of an existing grid (e.g. read with `read_csv`) interpolated at
+///   the nodes, the existing grid may have a different resolution or extent
+#[derive(Clone)]
+pub enum InitialGuess<'g> {
+    Zero,
+    Constant(f64),
+    Random(Option<[u8; 32]>),
+    FixedMean,
+    Harmonic,
+    Coarse { factor: usize, accepted_delta: f64, over_relaxation: f64 },
+    Existing(&'g Grid),
+}
+
+impl Grid {
+    /// fills the dynamic nodes according to `guess`, `task` is the builder this grid was created
+    /// with. Only a coarse guess can fail, if the coarse grid can not be built or does not
+    /// converge
+    pub(crate) fn apply_initial_guess(&mut self, guess: &InitialGuess, task: &GridBuilder)
+        -> Result<(), GridError> {
+        match *guess {
+            InitialGuess::Zero => self.fill_dynamic(|_, _| 0.0),
+            InitialGuess::Constant(value) => self.fill_dynamic(|_, _| value),
+            InitialGuess::Random(seed) => {
+                let mut random = if let Some(seed) = seed {
+                    ChaChaRng::from_seed(seed)
+                } else {
+                    ChaChaRng::from_entropy()
+                };
+                self.fill_dynamic(|_, _| random.gen_range(0.0, 100.0));
+            }
+            InitialGuess::FixedMean => {
+                let fixed = self.fixed_mask();
+                let (sum, count) = self.nodes.iter().zip(fixed.iter()).filter(|&(_, &f)| f)
+                    .fold((0.0, 0), |(sum, count), (&v, _)| (sum + v, count + 1));
+                let mean = if count > 0 { sum / count as f64 } else { 0.0 };
+                self.fill_dynamic(|_, _| mean);
+            }
+            InitialGuess::Harmonic => self.harmonic_guess(),
+            InitialGuess::Coarse { factor, accepted_delta, over_relaxation } => {
+                // the factor is checked by the builder, but a coarse grid can still be invalid,
+                // e.g. if an open border no longer encloses the coarsened electrodes
+                let mut coarse = task.clone().coarsen(factor)
+                    .initial_guess(InitialGuess::Harmonic).build()
+                    .map_err(|error| GridError::InvalidInitialGuess(format!(
+                        "the grid coarsened by {} is invalid: {}", factor, error)))?;
+                if !iterate(accepted_delta, None, |_| Some(coarse.sweep(over_relaxation))).1 {
+                    return Err(GridError::InvalidInitialGuess(format!(
+                        "the grid coarsened by {} did not converge", factor)));
+                }
+                self.interpolate_from(&coarse);
+            }
+            InitialGuess::Existing(grid) => self.interpolate_from(grid),
+        }
+        Ok(())
+    }
+
+    /// sets every dynamic node to `value(column, row)`, in the order of the dynamic nodes
+    fn fill_dynamic<F: FnMut(usize, usize) -> f64>(&mut self, mut value: F) {
+        for &i in self.dynamic_nodes_indices.iter() {
+            self.nodes[i] = value(i % self.width, i / self.width);
+        }
+    }
+
+    /// sets the dynamic nodes to the potential of `other` at the same external coordinates
+    fn interpolate_from(&mut self, other: &Grid) {
+        let scale = self.scale.clone();
+        self.fill_dynamic(|column, row| {
+            let (x, y) = scale.to_external(column as f64, row as f64);
+            other.sample_clamped(x, y, Interpolation::Bilinear)
+        });
+    }
+
+    fn harmonic_guess(&mut self) {
+        let fixed = self.fixed_mask();
+        let (width, height) = (self.width as isize, self.height() as isize);
+        let mut guess = Vec::with_capacity(self.dynamic_nodes_indices.len());
+        for &i in self.dynamic_nodes_indices.iter() {
+            let (column, row) = ((i % self.width) as isize, (i / self.width) as isize);
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for &(d_column, d_row) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+                // walking in this direction until a fixed node or the end of the grid (behind an
+                // insulating border) is hit
+                let (mut c, mut r, mut distance) = (column, row, 0.0);
+                loop {
+                    c += d_column;
+                    r += d_row;
+                    distance += 1.0;
+                    if c < 0 || r < 0 || c >= width || r >= height { break; }
+                    let j = (r * width + c) as usize;
+                    if fixed[j] {
+                        sum += self.nodes[j] / distance;
+                        weights += 1.0 / distance;
+                        break;
+                    }
+                }
+            }
+            guess.push(if weights > 0.0 { sum / weights } else { 0.0 });
+        }
+        let mut guess = guess.into_iter();
+        self.fill_dynamic(|_, _| guess.next().unwrap_or(0.0));
+    }
+}
@@ -1,13 +1,67 @@
 extern crate rand;
 
 mod thread_pool;
+mod checksum;
+mod sample;
+mod field;
+mod profile;
+mod render;
+mod svg;
+mod vtk;
+mod npy;
+mod csv;
+mod checkpoint;
+mod initial_guess;
+mod cascade;
+mod convergence;
+mod builder;
+mod units;
+mod stencil;
+mod grading;
+mod adaptive;
+mod grid3;
+mod conductor;
+mod open;
+mod heat;
+mod current;
 pub mod plot;
 
-use rand::prelude::*;
-use rand::ChaChaRng;
+pub use sample::{Interpolation, SampleError};
+pub use profile::{ProfileSample, profile_to_csv};
+pub use render::{Colormap, HeatMap, Image};
+pub use svg::SvgPlot;
+pub use npy::{read_npy, read_npz};
+pub use csv::{read_csv, CsvError};
+pub use checkpoint::{Checkpoint, SolverState};
+pub use initial_guess::InitialGuess;
+pub use builder::{BoundaryCondition, GridBuilder};
+pub use units::{LengthUnit, PotentialUnit, Units};
+pub use grading::Grading;
+pub use stencil::{Geometry, StencilKind};
+pub use conductor::{Conductor, Shape};
+pub use open::OpenBoundary;
+pub use heat::{TimeStepping, Transient, TransientError};
+pub use current::{Conduction, ConductivityBox};
+pub use adaptive::{AdaptiveGrid, Refinement};
+pub use grid3::{FixedBox3, Grid3, Grid3Builder, Plane, Scale3};
+pub use convergence::{convergence_study, ConvergenceError, ConvergenceStudy, ProbeConvergence};
+
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::Write;
 
+use stencil::{BorderStencils, Discretization, NinePointStencils, Stencil, Stencils};
+use open::OpenBorder;
+use thread_pool::WeightedMean;
+
+/// most passes of `Grid::evaluate_open`
+const MAX_OPEN_PASSES: usize = 1000;
+
+/// most iterations of one solve, see `iterate`
+const MAX_ITERATIONS: usize = 1_000_000;
+
 /// A simple struct for defining the elements in the model that have fixed potentials
 ///
 /// # Fields
@@ -17,7 +71,7 @@ use std::io::Write;
 /// * `width` - width of the box
 /// * `height` - height of the box
 /// * `potential` - fixed potential across the box
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FixedBox {
     pub x: isize,
     pub y: isize,
@@ -26,23 +80,259 @@ pub struct FixedBox {
     pub potential: f64,
 }
 
+/// Everything that can be wrong with the description of a grid, see `Grid::new`
+#[derive(Debug, Clone, PartialEq)]
+pub enum GridError {
+    /// the grid is zero units wide or high
+    ZeroSize { width: usize, height: usize },
+    /// the scale can not be used, e.g. a spacing of zero
+    InvalidScale(String),
+    /// fixed box number `index` (position in `fixed_elements`) reaches outside of the grid
+    OutOfBounds { index: usize, fixed_box: FixedBox },
+    /// fixed boxes number `first` and `second` overlap but have different potentials, (`x`, `y`)
+    /// is one of the nodes both of them cover
+    OverlapConflict { first: usize, second: usize, x: f64, y: f64 },
+    /// the three dimensional grid is zero units wide, high or deep
+    ZeroVolume { width: usize, height: usize, depth: usize },
+    /// fixed box number `index` of a three dimensional grid reaches outside of it
+    OutOfBounds3 { index: usize, fixed_box: FixedBox3 },
+    /// the three dimensional fixed boxes `first` and `second` overlap but have different
+    /// potentials, (`x`, `y`, `z`) is one of the nodes both of them cover
+    OverlapConflict3 { first: usize, second: usize, x: f64, y: f64, z: f64 },
+    /// the shape of conductor number `index` can not be used
+    InvalidConductor { index: usize, reason: String },
+    /// conductor number `index` covers the node at (`x`, `y`) which a fixed box or an earlier
+    /// conductor already fixes to a different potential
+    ConductorConflict { index: usize, x: f64, y: f64 },
+    /// the open border can not be used, e.g. an electrode is outside of its contour
+    InvalidOpenBoundary(String),
+    /// the conduction can not be used, e.g. with an axisymmetric grid
+    InvalidConduction(String),
+    /// the initial guess can not be used, e.g. a coarsening factor of 0
+    InvalidInitialGuess(String),
+    /// the terminal of a current or resistance reaches outside of the grid
+    TerminalOutOfBounds(FixedBox),
+    /// the terminal of a current or resistance covers no node, a dynamic node or nodes with
+    /// different potentials, so it has no potential of its own
+    FloatingTerminal(FixedBox),
+    /// no current flows between the terminals of a resistance, e.g. both are at the same
+    /// potential or the sheet between them is cut
+    NoCurrent,
+    /// the solve with a node spacing of `spacing` (x and y) diverged or did not converge within
+    /// the iteration limit, e.g. with an over relaxation of 2 or more
+    NotConverged { spacing: (f64, f64) },
+    /// a solver does not support a feature of the task, e.g. adaptive refinement of graded axes
+    Unsupported(String),
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GridError::ZeroSize { width, height } =>
+                write!(f, "a grid of {} x {} units has no nodes", width, height),
+            GridError::InvalidScale(ref message) => write!(f, "invalid scale: {}", message),
+            GridError::OutOfBounds { index, ref fixed_box } =>
+                write!(f, "fixed box {} ({:?}) reaches outside of the grid", index, fixed_box),
+            GridError::OverlapConflict { first, second, x, y } =>
+                write!(f, "fixed boxes {} and {} have different potentials but both cover \
+                           ({}, {})", first, second, x, y),
+            GridError::ZeroVolume { width, height, depth } =>
+                write!(f, "a grid of {} x {} x {} units has no nodes", width, height, depth),
+            GridError::OutOfBounds3 { index, ref fixed_box } =>
+                write!(f, "fixed box {} ({:?}) reaches outside of the grid", index, fixed_box),
+            GridError::OverlapConflict3 { first, second, x, y, z } =>
+                write!(f, "fixed boxes {} and {} have different potentials but both cover \
+                           ({}, {}, {})", first, second, x, y, z),
+            GridError::InvalidConductor { index, ref reason } =>
+                write!(f, "conductor {} is invalid: {}", index, reason),
+            GridError::ConductorConflict { index, x, y } =>
+                write!(f, "conductor {} covers ({}, {}) which already has a different potential",
+                       index, x, y),
+            GridError::InvalidOpenBoundary(ref message) =>
+                write!(f, "invalid open boundary: {}", message),
+            GridError::InvalidConduction(ref message) =>
+                write!(f, "invalid conduction: {}", message),
+            GridError::InvalidInitialGuess(ref message) =>
+                write!(f, "invalid initial guess: {}", message),
+            GridError::TerminalOutOfBounds(ref terminal) =>
+                write!(f, "terminal {:?} reaches outside of the grid", terminal),
+            GridError::FloatingTerminal(ref terminal) =>
+                write!(f, "terminal {:?} does not cover nodes fixed to one potential", terminal),
+            GridError::NoCurrent => write!(f, "no current flows between the terminals"),
+            GridError::NotConverged { spacing: (x, y) } =>
+                write!(f, "the solve with a node spacing of {} x {} did not converge", x, y),
+            GridError::Unsupported(ref message) => write!(f, "unsupported: {}", message),
+        }
+    }
+}
+
+impl Error for GridError {}
+
+/// One axis of the coordinate system, internal indices (columns or rows) are translated to
+/// external coordinates with `origin ± index * spacing`, or `origin ± distance` with the
+/// distance interpolated from the node positions on graded axes
+///
+///  # Fields
+///
+/// * `origin` - external coordinate of the first node (column or row 0)
+/// * `spacing` - distance between two neighbouring nodes, the smallest one on graded axes
+/// * `invert` - if this is true the coordinate decreases with the index (x goes from right to
+///   left, y from top to bottom; data is not flipped)
+/// * `graded` - distances of the nodes from `origin` (starting at 0, strictly increasing, at
+///   least two) for graded axes, `None` for evenly spaced ones. A graded axis has exactly as
+///   many nodes as distances
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis {
+    pub origin: f64,
+    pub spacing: f64,
+    pub invert: bool,
+    pub graded: Option<Vec<f64>>,
+}
+
+impl Axis {
+    /// the (not rounded) index of the external `coordinate`
+    pub fn to_internal(&self, coordinate: f64) -> f64 {
+        let distance = if self.invert { self.origin - coordinate }
+            else { coordinate - self.origin };
+        match self.graded {
+            None => distance / self.spacing,
+            Some(ref positions) => {
+                // piecewise linear between the nodes, extrapolated with the first and last cell
+                let cell = positions.partition_point(|&p| p <= distance).saturating_sub(1)
+                    .min(positions.len() - 2);
+                cell as f64 + (distance - positions[cell])
+                    / (positions[cell + 1] - positions[cell])
+            }
+        }
+    }
+
+    /// The inverse of `to_internal`
+    pub fn to_external(&self, index: f64) -> f64 {
+        let distance = match self.graded {
+            None => index * self.spacing,
+            Some(ref positions) => {
+                let cell = (index.floor().max(0.0) as usize).min(positions.len() - 2);
+                positions[cell] + (index - cell as f64) * (positions[cell + 1] - positions[cell])
+            }
+        };
+        if self.invert { self.origin - distance } else { self.origin + distance }
+    }
+
+    /// true if the nodes are not evenly spaced
+    pub fn is_graded(&self) -> bool {
+        self.graded.is_some()
+    }
+
+    /// distance between the nodes `index` and `index + 1`
+    pub(crate) fn node_spacing(&self, index: usize) -> f64 {
+        match self.graded {
+            None => self.spacing,
+            Some(ref positions) => positions[index + 1] - positions[index],
+        }
+    }
+
+    /// the biggest distance between two neighbouring nodes
+    pub(crate) fn max_spacing(&self) -> f64 {
+        match self.graded {
+            None => self.spacing,
+            Some(ref positions) => positions.windows(2).map(|p| p[1] - p[0])
+                .fold(0.0, f64::max),
+        }
+    }
+
+    /// the axis with every `factor`th node, graded axes keep their last node as well
+    pub(crate) fn coarsened(&self, factor: usize) -> Axis {
+        let mut axis = self.clone();
+        match self.graded {
+            None => axis.spacing *= factor as f64,
+            Some(ref positions) => {
+                let mut coarse: Vec<f64> = positions.iter().cloned().step_by(factor).collect();
+                if (positions.len() - 1) % factor != 0 {
+                    coarse.push(positions[positions.len() - 1]);
+                }
+                axis.spacing = smallest_step(&coarse);
+                axis.graded = Some(coarse);
+            }
+        }
+        axis
+    }
+
+    /// Infers the axis from the external coordinates of its nodes, as they are written to files.
+    /// Evenly spaced coordinates give a uniform axis, others a graded one. Fails if there are less
+    /// than two coordinates or they are not strictly increasing or decreasing
+    pub fn from_coordinates(coordinates: &[f64], name: &str) -> Result<Axis, String> {
+        const TOLERANCE: f64 = 1e-6;
+        if coordinates.len() < 2 {
+            return Err(format!("the {} axis needs at least two coordinates", name));
+        }
+        // the step over all coordinates is more accurate than the one between the first two
+        let step = (coordinates[coordinates.len() - 1] - coordinates[0])
+            / (coordinates.len() - 1) as f64;
+        if !step.is_finite() || step == 0.0 || !coordinates[0].is_finite() {
+            return Err(format!("the spacing of the {} axis ({}) is invalid", name, step));
+        }
+        let evenly_spaced = coordinates.iter().enumerate().all(|(i, &coordinate)| {
+            (coordinate - coordinates[0] - i as f64 * step).abs() <= TOLERANCE * step.abs()
+        });
+        if evenly_spaced {
+            return Ok(Axis { origin: coordinates[0], spacing: step.abs(), invert: step < 0.0,
+                graded: None });
+        }
+
+        let invert = step < 0.0;
+        let positions: Vec<f64> = coordinates.iter()
+            .map(|&c| if invert { coordinates[0] - c } else { c - coordinates[0] }).collect();
+        for i in 1..positions.len() {
+            if positions[i] <= positions[i - 1] || !positions[i].is_finite() {
+                return Err(format!("coordinate {} ({}) of the {} axis is not strictly {}", i,
+                                   coordinates[i], name,
+                                   if invert { "decreasing" } else { "increasing" }));
+            }
+        }
+        Ok(Axis { origin: coordinates[0], spacing: smallest_step(&positions), invert,
+            graded: Some(positions) })
+    }
+}
+
+/// smallest distance between two neighbouring positions
+fn smallest_step(positions: &[f64]) -> f64 {
+    positions.windows(2).map(|p| p[1] - p[0]).fold(f64::INFINITY, f64::min)
+}
+
 /// Part of the Grid struct it contains info on the coordinate system the grid was defined with
 /// mainly used to translate between internal and external coordinates
 ///
 ///  # Fields
 ///
-/// * `nodes_per_unit` - grid resolution
-/// * `x_offset` - offset to the x axis (internal coordinates are unsigned so this is required to
-/// reflect the coordinates of the task.
-/// * `y_offset` - offset to the y axis
-/// * `invert_x`- if this is true the x axis will go from right to left (data is not filliped)
-/// * `invert_y`- if this is true the y axis will go from top to bottom (data is not filliped)
+/// * `x` - the x axis (columns)
+/// * `y` - the y axis (rows), the spacing may differ from the one of the x axis
+/// * `units` - units of the coordinates and the potential
+#[derive(Debug, Clone, PartialEq)]
 pub struct Scale {
-    pub nodes_per_unit: usize,
-    pub x_offset: isize,
-    pub y_offset: isize,
-    pub invert_x: bool,
-    pub invert_y: bool,
+    pub x: Axis,
+    pub y: Axis,
+    pub units: Units,
+}
+
+impl Scale {
+    /// Translates external coordinates to internal ones (column and row). Unlike
+    /// `convert_coordinates` the result is not rounded so it can lie between nodes
+    pub fn to_internal(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.x.to_internal(x), self.y.to_internal(y))
+    }
+
+    /// The inverse of `to_internal`
+    pub fn to_external(&self, column: f64, row: f64) -> (f64, f64) {
+        (self.x.to_external(column), self.y.to_external(row))
+    }
+
+    /// Infers the scale from the external coordinates of the node columns (`x`) and rows (`y`),
+    /// as they are written to files. Fails if the coordinates are not strictly monotonic. The
+    /// units are the default ones
+    pub fn from_axes(x: &[f64], y: &[f64]) -> Result<Scale, String> {
+        Ok(Scale { x: Axis::from_coordinates(x, "x")?, y: Axis::from_coordinates(y, "y")?,
+            units: Units::default() })
+    }
 }
 
 /// Storing the grid with all its nodes
@@ -51,172 +341,277 @@ pub struct Scale {
 ///
 /// * `nodes` - the literal nodes of the Grid as an array
 /// * `width` - each set of `width` elements of the `nodes` array form one row of nodes in the x
-/// direction
+///   direction
 /// * `dynamic_nodes_indices` - all the indices of nodes that can actually change. Each cycle of the
-/// calculation will iterate of these.
+///   calculation will iterate of these.
 /// * `scale` - info for plots
-pub struct Grid<'s> {
+/// * `stencils` - the weights of the neighbours of every dynamic node, in the same order as
+///   `dynamic_nodes_indices`; they follow from `scale` and `geometry` when the grid is created
+/// * `discretization` - geometry, kind of stencil, conductors and source the `stencils` were
+///   made for
+/// * `open` - the border nodes `evaluate_open` sets from a multipole expansion, if the border is
+///   open
+pub struct Grid {
     pub nodes: Vec<f64>,
     pub width: usize,
     dynamic_nodes_indices: Vec<usize>,
-    pub scale: &'s Scale,
+    pub scale: Scale,
+    stencils: Vec<Stencil>,
+    discretization: Discretization,
+    open: Option<OpenBorder>,
 }
 
-impl<'s> Grid<'s> {
-    pub fn new(width: usize, height: usize, fixed_elements: &Vec<FixedBox>, scale: &'s Scale,
-               seed: Option<[u8; 32]>) -> Grid<'s> {
-        let width = width * scale.nodes_per_unit;
-        let height= height * scale.nodes_per_unit;
-
-        let mut nodes = vec![0.0; width*height];
-        let mut  fixed_nodes_indices = Vec::with_capacity(width*height);
-
-        // adding all indices of the border to the ´fixed_nodes_indices´ array
-        let bottom_border_start_index = width * (height-1);
-        for i in 0..width {
-            //top border
-            fixed_nodes_indices.push(i);
-            //left border
-            fixed_nodes_indices.push(i*width);
-            //right border
-            fixed_nodes_indices.push(i*width+width-1);
-            //bottom border
-            fixed_nodes_indices.push(bottom_border_start_index + i);
+impl Grid {
+    /// Creates a grid of `width` x `height` units with random noise on the dynamic nodes, a
+    /// shorthand for the most common use of `GridBuilder`. Fails if the grid is empty, the scale
+    /// is invalid, a fixed box is not completely inside of the grid or two overlapping fixed boxes
+    /// have different potentials
+    pub fn new(width: usize, height: usize, fixed_elements: &[FixedBox], scale: &Scale,
+               seed: Option<[u8; 32]>) -> Result<Grid, GridError> {
+        let builder = GridBuilder::new().size(width, height).scale(scale.clone())
+            .electrodes(fixed_elements);
+        match seed {
+            Some(seed) => builder.seed(seed).build(),
+            None => builder.build(),
         }
+    }
 
-        // adding all indices of the fixed boxes to the ´fixed_nodes_indices´ array
-        for fixed_box in fixed_elements.iter() {
-            //TODO check constraints
-            let top_left_index = convert_coordinates(fixed_box.x, fixed_box.y, scale, width);
-            for i in 0..fixed_box.height * scale.nodes_per_unit {
-                for j in 0..fixed_box.width * scale.nodes_per_unit {
-                    let index = top_left_index + j + i * width;
-                    fixed_nodes_indices.push(index);
-                    nodes[index] = fixed_box.potential;
-                }
-            }
-        }
+    /// Creates a grid from existing nodes, `fixed` marks the nodes with a fixed potential. The
+    /// border is always fixed no matter what `fixed` says. Graded axes of `scale` need exactly
+    /// one position per column (row)
+    pub fn from_parts(nodes: Vec<f64>, width: usize, fixed: &[bool], scale: Scale) -> Grid {
+        Grid::from_parts_with(nodes, width, fixed, scale, Discretization::default())
+    }
 
-        //"inverting" the `fixed_nodes_indices` vector
-        // this is the fastest method  i came up with if done wrong this will take a long time
-        // (i.e. using .contains())
-        fixed_nodes_indices.sort_unstable();
-        fixed_nodes_indices.dedup();
-        let mut dynamic_nodes_indices =
-            Vec::with_capacity(nodes.len()-fixed_nodes_indices.len());
-        let mut j = 0;
-        for i in 0..nodes.len() {
-            if i != fixed_nodes_indices[j] {
-                dynamic_nodes_indices.push(i);
-            } else { j += 1; }
+    /// `from_parts` for any discretization, the axis of an axisymmetric grid is not part of the
+    /// border and an insulating border follows `fixed`. The kind of stencil has to suit the
+    /// scale and the geometry
+    pub(crate) fn from_parts_with(nodes: Vec<f64>, width: usize, fixed: &[bool], scale: Scale,
+                                  discretization: Discretization) -> Grid {
+        assert_eq!(nodes.len(), fixed.len(), "Every node needs an entry in `fixed`");
+        assert!(width > 0 && nodes.len() % width == 0, "The nodes do not form full rows");
+        let height = nodes.len() / width;
+        let first_column = if discretization.geometry.has_axis(&scale) { 0 } else { 1 };
+        let dynamic_border = discretization.dynamic_border();
+        let dynamic_nodes_indices = (0..nodes.len()).filter(|&i| {
+            let (column, row) = (i % width, i / width);
+            !fixed[i] && (dynamic_border || column >= first_column && column < width - 1 &&
+                row > 0 && row < height - 1)
+        }).collect();
+        Grid::assemble(nodes, width, dynamic_nodes_indices, scale, discretization)
+    }
+
+    /// the grid with the stencils of its dynamic nodes, nodes that do not conduct to any
+    /// neighbour are fixed
+    fn assemble(nodes: Vec<f64>, width: usize, mut dynamic_nodes_indices: Vec<usize>,
+                scale: Scale, discretization: Discretization) -> Grid {
+        let mut stencils = Stencil::for_nodes(&dynamic_nodes_indices, width, nodes.len() / width,
+                                              &scale, &discretization);
+        if stencils.iter().any(|stencil| stencil.diagonal == 0.0) {
+            let (kept, kept_stencils) = dynamic_nodes_indices.into_iter().zip(stencils)
+                .filter(|&(_, stencil)| stencil.diagonal != 0.0).unzip();
+            dynamic_nodes_indices = kept;
+            stencils = kept_stencils;
         }
+        Grid { nodes, width, dynamic_nodes_indices, scale, stencils, discretization, open: None }
+    }
 
-        //filling the nodes with noise
-        let mut random = if let Some(seed) = seed {
-            ChaChaRng::from_seed(seed)
-        } else {
-            ChaChaRng::from_entropy()
-        };
-        for &i in dynamic_nodes_indices.iter() {
-            nodes[i] = random.gen_range(0.0, 100.0);
+    /// whether the grid is planar or axisymmetric
+    pub fn geometry(&self) -> Geometry {
+        self.discretization.geometry
+    }
+
+    /// the stencil the grid is evaluated with
+    pub fn stencil_kind(&self) -> StencilKind {
+        self.discretization.stencil_kind
+    }
+
+    /// the conductors of the grid, their nodes are fixed
+    pub fn conductors(&self) -> &[Conductor] {
+        &self.discretization.conductors
+    }
+
+    /// the conductivities of a grid for current flow, `None` if it has none
+    pub fn conduction(&self) -> Option<&Conduction> {
+        self.discretization.conduction.as_ref()
+    }
+
+    /// the source of the Poisson equation at every node, in the same order as `nodes`; `None`
+    /// if the grid solves the Laplace equation
+    pub fn source(&self) -> Option<&[f64]> {
+        if self.discretization.source.is_empty() { None } else { Some(&self.discretization.source) }
+    }
+
+    /// the settings of the open border, `None` if the border is fixed
+    pub fn open_boundary(&self) -> Option<OpenBoundary> {
+        self.open.as_ref().map(|open| open.settings)
+    }
+
+    /// number of nodes in the y direction
+    pub fn height(&self) -> usize {
+        self.nodes.len() / self.width
+    }
+
+    /// true for every node with a fixed potential (border and fixed boxes), in the same order as
+    /// `nodes`
+    pub fn fixed_mask(&self) -> Vec<bool> {
+        let mut mask = vec![true; self.nodes.len()];
+        for &i in self.dynamic_nodes_indices.iter() {
+            mask[i] = false;
         }
+        mask
+    }
 
-        Grid { nodes, width, dynamic_nodes_indices, scale}
+    /// one iteration over all dynamic nodes (successive over relaxation), returns the biggest
+    /// delta of this iteration
+    pub fn sweep(&mut self, over_relaxation: f64) -> f64 {
+        let (stencils, width) = (&self.stencils, self.width);
+        match self.discretization.stencil_kind {
+            StencilKind::FivePoint if self.discretization.dynamic_border() =>
+                relax(&mut self.nodes, &self.dynamic_nodes_indices,
+                      &BorderStencils { stencils, width }, over_relaxation),
+            StencilKind::FivePoint => relax(&mut self.nodes, &self.dynamic_nodes_indices,
+                                            &Stencils { stencils, width }, over_relaxation),
+            StencilKind::NinePoint => relax(&mut self.nodes, &self.dynamic_nodes_indices,
+                                            &NinePointStencils { stencils, width },
+                                            over_relaxation),
+        }
     }
 
     /// does the main work described in the task
     /// the function takes a ´accepted_delta´ which is used to determine when to stop iterating
     /// basically the function checks on every iteration what the biggest delta was and if it is
-    /// below ´accepted_delta´ the functions returns
+    /// below ´accepted_delta´ the functions returns. Returns whether it converged, it gives up
+    /// if the iteration diverges or after `MAX_ITERATIONS` iterations. An open border is solved
+    /// in passes like `evaluate_open`, the watch data then holds the iterations of all passes
     pub fn evaluate(&mut self, accepted_delta: f64, over_relaxation: f64, watch: (isize, isize),
-                    watch_data: &mut Vec<f64>) {
-        let watch = convert_coordinates(watch.0, watch.1, self.scale, self.width);
-        if self.nodes.len() <= watch { panic!("Watch is outside of the grid"); }
+                    watch_data: &mut Vec<f64>) -> bool {
+        let watch = node_index(watch.0, watch.1, &self.scale, self.width, self.height())
+            .expect("Watch is outside of the grid");
         watch_data.push(self.nodes[watch]);
 
-        let mut max_delta= accepted_delta + 1.0;
-        let mut iterations = 0;
-        while max_delta > accepted_delta {
-            iterations += 1;
-            max_delta = 0.0;
-            for &i in self.dynamic_nodes_indices.iter() {
-                //summing up values to top, left, bottom and right and dividing by 4
-                let mut new_value = self.nodes[i-self.width];
-                new_value += self.nodes[i-1];
-                new_value += self.nodes[i+1];
-                new_value += self.nodes[i+self.width];
-                new_value /= 4.0;
-
-                let mut delta = self.nodes[i] - new_value;
-                self.nodes[i] -= over_relaxation*delta;
-                //checking if delta is new high
-                if delta.abs() > max_delta {max_delta = delta.abs()};
-            }
-            watch_data.push(self.nodes[watch]);
-            print!("\r{} iterations, max delta = {}", iterations, max_delta);
-            std::io::stdout().flush().expect("Could not flush stdout!");
-        }
-        watch_data.push(std::f64::NEG_INFINITY);
-        print!("\n");
+        let (_, converged) = self.open_passes(accepted_delta, |grid| {
+            iterate(accepted_delta, Some(""), |_| {
+                let max_delta = grid.sweep(over_relaxation);
+                watch_data.push(grid.nodes[watch]);
+                Some(max_delta)
+            }).1
+        });
+        watch_data.push(f64::NEG_INFINITY);
+        converged
     }
 
     // is the exact same as evaluate just with a fixed iteration count
     pub fn evaluate_for(&mut self, over_relaxation: f64, watch: (isize, isize),
                     watch_data: &mut Vec<f64>, iterations: usize) {
-        let watch = convert_coordinates(watch.0, watch.1, self.scale, self.width);
-        if self.nodes.len() <= watch { panic!("Watch is outside of the grid"); }
+        let watch = node_index(watch.0, watch.1, &self.scale, self.width, self.height())
+            .expect("Watch is outside of the grid");
         watch_data.push(self.nodes[watch]);
 
         for _i in 0..iterations {
-            for &j in self.dynamic_nodes_indices.iter() {
-                //summing up values to top, left, bottom and right and dividing by 4
-                let mut new_value = self.nodes[j-self.width];
-                new_value += self.nodes[j-1];
-                new_value += self.nodes[j+1];
-                new_value += self.nodes[j+self.width];
-                new_value /= 4.0;
-
-                let mut delta = self.nodes[j] - new_value;
-                self.nodes[j] -= over_relaxation*delta;
-            }
+            self.sweep(over_relaxation);
             watch_data.push(self.nodes[watch]);
         }
-        watch_data.push(std::f64::NEG_INFINITY);
+        watch_data.push(f64::NEG_INFINITY);
     }
 
     /// does the exact same ting ´evaluate()´ does just on multiple threads at once
     /// the `threads´ argument takes the number of slave threads you want so the optimal number
-    /// should be the number of threads your cpu supports - 1 (for the master thread)
+    /// should be the number of threads your cpu supports - 1 (for the master thread). Returns
+    /// whether it converged and solves an open border in passes, like `evaluate`
     pub fn evaluate_multi_thread(&mut self, accepted_delta: f64, over_relaxation: f64,
-                                 threads: usize) {
-        let pool = thread_pool::ThreadPool::new(threads, self, over_relaxation);
-        pool.evaluate(accepted_delta, self.dynamic_nodes_indices.len());
+                                 threads: usize) -> bool {
+        self.open_passes(accepted_delta, |grid| {
+            grid.solve_multi_thread(accepted_delta, over_relaxation, threads)
+        }).1
+    }
+
+    /// one solve of `evaluate_multi_thread` with the border as it is
+    fn solve_multi_thread(&mut self, accepted_delta: f64, over_relaxation: f64, threads: usize)
+        -> bool {
+        let (stencils, width) = (&self.stencils, self.width);
+        // the diagonal neighbours are one further away than the ones above and below
+        let pool = match self.discretization.stencil_kind {
+            StencilKind::FivePoint if self.discretization.dynamic_border() =>
+                thread_pool::ThreadPool::new(
+                    threads, &mut self.nodes, &self.dynamic_nodes_indices, width,
+                    &BorderStencils { stencils, width }, over_relaxation),
+            StencilKind::FivePoint => thread_pool::ThreadPool::new(
+                threads, &mut self.nodes, &self.dynamic_nodes_indices, width,
+                &Stencils { stencils, width }, over_relaxation),
+            StencilKind::NinePoint => thread_pool::ThreadPool::new(
+                threads, &mut self.nodes, &self.dynamic_nodes_indices, width + 1,
+                &NinePointStencils { stencils, width }, over_relaxation),
+        };
+        pool.evaluate(accepted_delta, self.dynamic_nodes_indices.len())
+    }
+
+    /// Solves the grid like `evaluate` (without watch) and then, if the border is open, sets the
+    /// border from the expansion fitted to the contour and solves again until no border node
+    /// changes by more than `accepted_delta` or `MAX_OPEN_PASSES` passes are done, every pass
+    /// ends with a solve of the new border. Returns the number of passes (0 for a fixed border)
+    /// and whether the border and the last solve converged
+    pub fn evaluate_open(&mut self, accepted_delta: f64, over_relaxation: f64) -> (usize, bool) {
+        self.open_passes(accepted_delta, |grid| {
+            iterate(accepted_delta, None, |_| Some(grid.sweep(over_relaxation))).1
+        })
+    }
+
+    /// the passes of `evaluate_open` with `solve` solving the grid for the current border and
+    /// returning whether it converged
+    fn open_passes<F: FnMut(&mut Grid) -> bool>(&mut self, accepted_delta: f64, mut solve: F)
+        -> (usize, bool) {
+        let mut converged = solve(self);
+        if self.open.is_none() {
+            return (0, converged);
+        }
+        for passes in 1..MAX_OPEN_PASSES + 1 {
+            let mut max_change: f64 = 0.0;
+            if let Some(ref open) = self.open {
+                let potentials = open.potentials(&self.nodes, self.width, &self.scale,
+                                                 self.discretization.geometry);
+                for (&i, &potential) in open.border.iter().zip(potentials.iter()) {
+                    max_change = max_change.max((self.nodes[i] - potential).abs());
+                    self.nodes[i] = potential;
+                }
+            }
+            converged = solve(self);
+            if max_change <= accepted_delta {
+                return (passes, converged);
+            }
+        }
+        (MAX_OPEN_PASSES, false)
     }
 
     /// generates a csv file at the specified path containing the nodes
     /// these files then can be opened in a spread sheet program like excel for plotting.
     /// The 100 in the string literals is a filler
-    /// the first row and column are filled with axis info
+    /// the first row and column are filled with axis info, `read_csv` reads these files again
+    /// (with millimeters and volts, see `to_csv_with_units`)
     pub fn to_csv(&self, path: &str) -> std::io::Result<()> {
-        let mut csv = File::create(path)?;
+        File::create(path)?.write_all(self.csv_string(false).as_bytes())
+    }
+
+    /// the same as `to_csv` with a comment line before the axis info that names the units, so
+    /// `read_csv` restores them. Spread sheet programs may not skip the comment
+    pub fn to_csv_with_units(&self, path: &str) -> std::io::Result<()> {
+        File::create(path)?.write_all(self.csv_string(true).as_bytes())
+    }
+
+    fn csv_string(&self, units_comment: bool) -> String {
         let mut file_string = String::with_capacity(self.nodes.len() * 20);
+        if units_comment {
+            file_string.push_str(&format!("# length unit {}, potential unit {}\n",
+                                          self.scale.units.length.symbol(),
+                                          self.scale.units.potential.symbol()));
+        }
 
         //create x coordinate labels
-        if self.scale.invert_x {
-            file_string.push_str(
-                &format!("100,{}", (self.width - 1) as f64 / self.scale.nodes_per_unit as f64
-                    - self.scale.x_offset as f64));
-            for x in 1..self.width {
-                file_string.push_str(&format!(",{}", (self.width - x) as f64
-                    / self.scale.nodes_per_unit as f64 - self.scale.x_offset as f64));
-            }
-        } else {
-            file_string.push_str(
-                &format!("100,{}", -self.scale.x_offset));
-            for x in 1..self.width {
-                file_string.push_str(&format!(",{}", x as f64 / self.scale.nodes_per_unit as
-                    f64 - self.scale.x_offset as f64));
-            }
+        // (the labels are the external coordinates of the columns and rows, so they are the
+        // inverse of `convert_coordinates` for inverted axes as well)
+        file_string.push_str("100");
+        for x in 0..self.width {
+            file_string.push_str(&format!(",{}", self.scale.to_external(x as f64, 0.0).0));
         }
 
         file_string.push('\n');
@@ -224,38 +619,93 @@ impl<'s> Grid<'s> {
 
 
         // write all the data of the grid to the file + y labels
-        if self.scale.invert_y {
-            for y in 0..height {
-                file_string.push_str(&format!("{}", (height - y) as f64
-                    / self.scale.nodes_per_unit as f64 - self.scale.y_offset as f64));
-                for x in 0..self.width {
-                    file_string.push_str(&format!(",{}", self.nodes[y*self.width+x]));
-                }
-                file_string.push('\n');
+        for y in 0..height {
+            file_string.push_str(&format!("{}", self.scale.to_external(0.0, y as f64).1));
+            for x in 0..self.width {
+                file_string.push_str(&format!(",{}", self.nodes[y*self.width+x]));
             }
-        } else {
-            for y in 0..height {
-                file_string.push_str(&format!("{}", y as f64 / self.scale.nodes_per_unit as
-                    f64 - self.scale.y_offset as f64));
-                for x in 0..self.width {
-                    file_string.push_str(&format!(",{}", self.nodes[y*self.width+x]));
-                }
-                file_string.push('\n');
+            file_string.push('\n');
+        }
+        file_string
+    }
+}
+
+/// one iteration of successive over relaxation over the `dynamic_nodes` with `stencil`, returns
+/// the biggest delta
+fn relax<M: WeightedMean>(nodes: &mut [f64], dynamic_nodes: &[usize], stencil: &M,
+                          over_relaxation: f64) -> f64 {
+    let mut max_delta = 0.0;
+    for (k, &i) in dynamic_nodes.iter().enumerate() {
+        //weighted sum of the values to the left and right and to the top and bottom (a
+        //quarter each for square cells)
+        let new_value = stencil.mean(nodes, k, i);
+
+        let delta = nodes[i] - new_value;
+        nodes[i] -= over_relaxation*delta;
+        //checking if delta is new high
+        if delta.abs() > max_delta {max_delta = delta.abs()};
+    }
+    max_delta
+}
+
+/// Calls `iteration` (with the number of the iteration, starting at 1) until the max delta it
+/// returns is at most `accepted_delta`, or `None` to stop early. Gives up if the max delta is not
+/// finite or after `MAX_ITERATIONS` iterations, since an over relaxation of 2 or more never
+/// converges. With `progress` the iterations and the max delta are printed after it. Returns the
+/// number of iterations and whether they converged
+fn iterate<F>(accepted_delta: f64, progress: Option<&str>, mut iteration: F) -> (usize, bool)
+    where F: FnMut(usize) -> Option<f64> {
+    let mut result = (MAX_ITERATIONS, false);
+    for count in 1..MAX_ITERATIONS + 1 {
+        let max_delta = match iteration(count) {
+            Some(max_delta) => max_delta,
+            None => {
+                result = (count - 1, false);
+                break;
             }
+        };
+        if let Some(label) = progress {
+            print!("\r{}{} iterations, max delta = {}", label, count, max_delta);
+            io::stdout().flush().expect("Could not flush stdout!");
         }
-        csv.write_all(file_string.as_bytes())?;
-        Ok(())
+        if max_delta <= accepted_delta || !max_delta.is_finite() {
+            result = (count, max_delta <= accepted_delta);
+            break;
+        }
+    }
+    if progress.is_some() {
+        println!();
     }
+    result
+}
+
+/// Takes coordinates and transforms them to the column and row of the nearest node, these are
+/// negative or too big for coordinates outside of the grid
+fn convert_coordinates(x: f64, y: f64, scale: &Scale) -> (isize, isize) {
+    let (column, row) = scale.to_internal(x, y);
+    (column.round() as isize, row.round() as isize)
 }
 
-/// Takes coordinates and transforms them to the index representing the point in the grid
-// TODO check for errors (out of bounds)
-fn convert_coordinates(x: isize, y: isize, scale: &Scale, width: usize) -> usize {
-    let x = if scale.invert_x { (-x + scale.x_offset) as usize * scale.nodes_per_unit }
-        else { (x + scale.x_offset) as usize * scale.nodes_per_unit };
-    let y = if scale.invert_y { (-y + scale.y_offset) as usize * scale.nodes_per_unit }
-        else { (y + scale.y_offset) as usize * scale.nodes_per_unit };
-    x+y*width
+/// columns and rows (half open ranges) covered by `fixed_box`, it starts at its `x` and `y` and
+/// extends in the direction of increasing indices (so to smaller coordinates on inverted axes)
+fn box_extent(fixed_box: &FixedBox, scale: &Scale) -> ((isize, isize), (isize, isize)) {
+    let (x, y) = (fixed_box.x as f64, fixed_box.y as f64);
+    let (width, height) = (fixed_box.width as f64, fixed_box.height as f64);
+    let (column, row) = convert_coordinates(x, y, scale);
+    let (end_column, end_row) = convert_coordinates(
+        if scale.x.invert { x - width } else { x + width },
+        if scale.y.invert { y - height } else { y + height }, scale);
+    ((column, end_column), (row, end_row))
+}
+
+/// index of the node at the coordinates `x` and `y` in a grid of `width` x `height` nodes, `None`
+/// if they are outside of it
+fn node_index(x: isize, y: isize, scale: &Scale, width: usize, height: usize) -> Option<usize> {
+    let (column, row) = convert_coordinates(x as f64, y as f64, scale);
+    if column < 0 || row < 0 || column as usize >= width || row as usize >= height {
+        return None;
+    }
+    Some(column as usize + row as usize * width)
 }
 
 /// Literally what the name says; used to plot the watch data
@@ -267,7 +717,7 @@ pub fn watch_data_to_csv(headers: &[String], watch_data: Vec<f64>, path: &str)
     let mut headers = headers.iter();
     file_string.push_str(headers.next().expect("No headers supplied").as_str());
     for &i in (&watch_data)[0..watch_data.len() - 1].iter() {
-        if i == std::f64::NEG_INFINITY {
+        if i == f64::NEG_INFINITY {
             file_string.push('\n');
             if let Some(header) = headers.next() {
                 file_string.push_str(header.as_str());
@@ -0,0 +1,365 @@
+//! # NumPy Files
+//!
+//! reading and writing grids as NumPy `.npy` arrays (one file per array) or as a `.npz` archive
+//! (an uncompressed zip file of `.npy` arrays, like `numpy.savez` writes them). The arrays are
+//!
+//! * `potential` - the nodes as a float64 array of shape (height, width)
+//! * `fixed` - bool array of the same shape, true for nodes with a fixed potential
+//! * `x` - external x coordinate of every column
+//! * `y` - external y coordinate of every row
+//!
+//! Only uncompressed archives can be read (not the ones written by `numpy.savez_compressed`).
+//! The units of the scale are not stored, grids read back have the default units.
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+
+use super::{Grid, Scale};
+use checksum::crc32;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+enum Data {
+    Float(Vec<f64>),
+    Bool(Vec<bool>),
+}
+
+struct Array {
+    shape: Vec<usize>,
+    data: Data,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// npy file (version 1.0) with a header describing `descr` and `shape` followed by `data`
+fn encode(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+                             descr, shape);
+    // the header is padded with spaces so the data starts at a multiple of 64 bytes
+    while (MAGIC.len() + 4 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut npy = Vec::with_capacity(MAGIC.len() + 4 + header.len() + data.len());
+    npy.extend_from_slice(MAGIC);
+    npy.extend_from_slice(&[1, 0]);
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    npy.extend_from_slice(data);
+    npy
+}
+
+fn encode_f64(shape: &[usize], data: &[f64]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len() * 8);
+    for value in data.iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    encode("<f8", shape, &bytes)
+}
+
+fn encode_bool(shape: &[usize], data: &[bool]) -> Vec<u8> {
+    let bytes: Vec<u8> = data.iter().map(|&b| b as u8).collect();
+    encode("|b1", shape, &bytes)
+}
+
+/// value of `key` in the header dictionary (everything up to the next `,` outside of brackets)
+fn header_value<'h>(header: &'h str, key: &str, name: &str) -> io::Result<&'h str> {
+    let start = header.find(&format!("'{}':", key))
+        .ok_or_else(|| invalid_data(format!("{}: header has no '{}'", name, key)))?
+        + key.len() + 3;
+    let rest = &header[start..];
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' | '}' if depth == 0 => return Ok(rest[..i].trim()),
+            _ => {}
+        }
+    }
+    Err(invalid_data(format!("{}: unterminated '{}' in header", name, key)))
+}
+
+fn decode(npy: &[u8], name: &str) -> io::Result<Array> {
+    if npy.len() < 10 || &npy[..6] != MAGIC {
+        return Err(invalid_data(format!("{} is not a npy file", name)));
+    }
+    let (header_start, header_length) = match npy[6] {
+        1 => (10, u16::from_le_bytes([npy[8], npy[9]]) as usize),
+        2 | 3 if npy.len() >= 12 =>
+            (12, u32::from_le_bytes([npy[8], npy[9], npy[10], npy[11]]) as usize),
+        version => return Err(invalid_data(
+            format!("{}: unsupported npy version {}", name, version))),
+    };
+    let data_start = header_start + header_length;
+    if npy.len() < data_start {
+        return Err(invalid_data(format!("{}: truncated header", name)));
+    }
+    let header = String::from_utf8_lossy(&npy[header_start..data_start]);
+
+    if header_value(&header, "fortran_order", name)? != "False" {
+        return Err(invalid_data(format!("{}: fortran order is not supported", name)));
+    }
+    let shape_string = header_value(&header, "shape", name)?;
+    let mut shape = Vec::new();
+    for dimension in shape_string.trim_matches(|c| c == '(' || c == ')').split(',') {
+        let dimension = dimension.trim();
+        if dimension.is_empty() { continue; }
+        shape.push(dimension.parse::<usize>().map_err(|_| invalid_data(
+            format!("{}: invalid shape {}", name, shape_string)))?);
+    }
+    let count = shape.iter().try_fold(1usize, |count, &dimension| count.checked_mul(dimension))
+        .ok_or_else(|| invalid_data(format!("{}: invalid shape {}", name, shape_string)))?;
+
+    let descr = header_value(&header, "descr", name)?.trim_matches('\'');
+    let data = &npy[data_start..];
+    let size = match descr {
+        "<f8" | ">f8" => 8,
+        "|b1" | "|u1" => 1,
+        _ => return Err(invalid_data(format!("{}: unsupported data type {}", name, descr))),
+    };
+    if data.len() / size < count {
+        return Err(invalid_data(format!("{}: expected {} values but the file is too short",
+                                        name, count)));
+    }
+    let data = match descr {
+        "<f8" => Data::Float(data.chunks(8).take(count).map(|b| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(b);
+            f64::from_le_bytes(bytes)
+        }).collect()),
+        ">f8" => Data::Float(data.chunks(8).take(count).map(|b| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(b);
+            f64::from_be_bytes(bytes)
+        }).collect()),
+        _ => Data::Bool(data[..count].iter().map(|&b| b != 0).collect()),
+    };
+    Ok(Array { shape, data })
+}
+
+impl Grid {
+    /// the four arrays described in the module documentation, named without extension
+    fn npy_arrays(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let height = self.height();
+        let x: Vec<f64> = (0..self.width)
+            .map(|column| self.scale.to_external(column as f64, 0.0).0).collect();
+        let y: Vec<f64> = (0..height)
+            .map(|row| self.scale.to_external(0.0, row as f64).1).collect();
+        vec![
+            ("potential", encode_f64(&[height, self.width], &self.nodes)),
+            ("fixed", encode_bool(&[height, self.width], &self.fixed_mask())),
+            ("x", encode_f64(&[self.width], &x)),
+            ("y", encode_f64(&[height], &y)),
+        ]
+    }
+
+    /// writes the arrays as `<prefix>_potential.npy`, `<prefix>_fixed.npy`, `<prefix>_x.npy` and
+    /// `<prefix>_y.npy`
+    pub fn to_npy(&self, prefix: &str) -> io::Result<()> {
+        for (name, npy) in self.npy_arrays() {
+            File::create(format!("{}_{}.npy", prefix, name))?.write_all(&npy)?;
+        }
+        Ok(())
+    }
+
+    /// writes all arrays into one (uncompressed) `.npz` archive at `path`
+    pub fn to_npz(&self, path: &str) -> io::Result<()> {
+        let mut zip = Vec::new();
+        let mut central_directory = Vec::new();
+        let arrays = self.npy_arrays();
+        for (name, npy) in arrays.iter() {
+            let name = format!("{}.npy", name);
+            let offset = zip.len() as u32;
+            let crc = crc32(npy);
+            // local file header: version 2.0, no flags, stored, 1980-01-01 00:00
+            zip.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+            zip.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+            zip.extend_from_slice(&crc.to_le_bytes());
+            zip.extend_from_slice(&(npy.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(npy.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&[0, 0]);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(npy);
+
+            central_directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            central_directory.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+            central_directory.extend_from_slice(&crc.to_le_bytes());
+            central_directory.extend_from_slice(&(npy.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(&(npy.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            // extra field, comment, disk, internal and external attributes
+            central_directory.extend_from_slice(&[0; 12]);
+            central_directory.extend_from_slice(&offset.to_le_bytes());
+            central_directory.extend_from_slice(name.as_bytes());
+        }
+        let directory_offset = zip.len() as u32;
+        zip.extend_from_slice(&central_directory);
+        zip.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        zip.extend_from_slice(&[0, 0, 0, 0]);
+        zip.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&directory_offset.to_le_bytes());
+        zip.extend_from_slice(&[0, 0]);
+
+        File::create(path)?.write_all(&zip)?;
+        Ok(())
+    }
+}
+
+/// reads the arrays written by `Grid::to_npy` with the same `prefix`, with the default units
+pub fn read_npy(prefix: &str) -> io::Result<Grid> {
+    let mut arrays = Vec::with_capacity(4);
+    for name in ["potential", "fixed", "x", "y"].iter() {
+        let path = format!("{}_{}.npy", prefix, name);
+        let mut npy = Vec::new();
+        File::open(&path)?.read_to_end(&mut npy)?;
+        arrays.push(decode(&npy, &path)?);
+    }
+    grid(arrays)
+}
+
+/// reads a `.npz` archive written by `Grid::to_npz` (or `numpy.savez` with the same arrays),
+/// with the default units
+pub fn read_npz(path: &str) -> io::Result<Grid> {
+    let mut zip = Vec::new();
+    File::open(path)?.read_to_end(&mut zip)?;
+    let entries = zip_entries(&zip, path)?;
+
+    let mut arrays = Vec::with_capacity(4);
+    for name in ["potential", "fixed", "x", "y"].iter() {
+        let file_name = format!("{}.npy", name);
+        let data = entries.iter().find(|entry| entry.0 == file_name)
+            .ok_or_else(|| invalid_data(format!("{} contains no {}", path, file_name)))?.1;
+        arrays.push(decode(data, &format!("{}/{}", path, file_name))?);
+    }
+    grid(arrays)
+}
+
+/// checks the arrays (potential, fixed, x and y) against each other and builds the grid
+fn grid(mut arrays: Vec<Array>) -> io::Result<Grid> {
+    let y = arrays.pop();
+    let x = arrays.pop();
+    let fixed = arrays.pop();
+    let potential = arrays.pop();
+    match (potential, fixed, x, y) {
+        (Some(Array { shape, data: Data::Float(nodes) }),
+            Some(Array { shape: fixed_shape, data: Data::Bool(fixed) }),
+            Some(Array { data: Data::Float(x), .. }),
+            Some(Array { data: Data::Float(y), .. })) => {
+            if shape.len() != 2 || fixed_shape != shape {
+                return Err(invalid_data(format!(
+                    "potential {:?} and fixed {:?} must be 2d arrays of the same shape",
+                    shape, fixed_shape)));
+            }
+            if shape[0] != y.len() || shape[1] != x.len() {
+                return Err(invalid_data(format!(
+                    "potential {:?} does not fit the coordinates x ({}) and y ({})",
+                    shape, x.len(), y.len())));
+            }
+            let scale = Scale::from_axes(&x, &y).map_err(invalid_data)?;
+            Ok(Grid::from_parts(nodes, shape[1], &fixed, scale))
+        }
+        _ => Err(invalid_data(String::from(
+            "potential, x and y must be float64 arrays and fixed a bool array"))),
+    }
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([bytes[i], bytes[i + 1]])
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+fn u64_at(bytes: &[u8], i: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&bytes[i..i + 8]);
+    u64::from_le_bytes(b)
+}
+
+/// names and contents of all files in a zip archive, using the central directory (including
+/// zip64 sizes since numpy always writes those)
+fn zip_entries<'z>(zip: &'z [u8], path: &str) -> io::Result<Vec<(String, &'z [u8])>> {
+    let corrupt = || invalid_data(format!("{} is not a valid zip file", path));
+    let end = (0..zip.len().saturating_sub(21)).rev()
+        .find(|&i| u32_at(zip, i) == 0x0605_4b50).ok_or_else(corrupt)?;
+    let mut entries = u16_at(zip, end + 10) as u64;
+    let mut directory = u32_at(zip, end + 16) as u64;
+    if (entries == 0xFFFF || directory == 0xFFFF_FFFF) && end >= 20 &&
+        u32_at(zip, end - 20) == 0x0706_4b50 {
+        let zip64_end = u64_at(zip, end - 12) as usize;
+        if zip64_end.checked_add(56).map_or(true, |end| end > zip.len()) ||
+            u32_at(zip, zip64_end) != 0x0606_4b50 {
+            return Err(corrupt());
+        }
+        entries = u64_at(zip, zip64_end + 32);
+        directory = u64_at(zip, zip64_end + 48);
+    }
+
+    // the number of entries comes from the file, so it is not used to reserve memory
+    let mut files = Vec::new();
+    let mut i = directory as usize;
+    for _ in 0..entries {
+        if i.checked_add(46).map_or(true, |end| end > zip.len()) || u32_at(zip, i) != 0x0201_4b50 {
+            return Err(corrupt());
+        }
+        let method = u16_at(zip, i + 10);
+        let mut compressed = u32_at(zip, i + 20) as u64;
+        let mut uncompressed = u32_at(zip, i + 24) as u64;
+        let name_length = u16_at(zip, i + 28) as usize;
+        let extra_length = u16_at(zip, i + 30) as usize;
+        let comment_length = u16_at(zip, i + 32) as usize;
+        let mut offset = u32_at(zip, i + 42) as u64;
+        if i + 46 + name_length + extra_length > zip.len() { return Err(corrupt()); }
+        let name = String::from_utf8_lossy(&zip[i + 46..i + 46 + name_length]).into_owned();
+
+        // zip64 extra field, it contains only the values that are 0xFFFFFFFF above
+        let mut extra = i + 46 + name_length;
+        let extra_end = extra + extra_length;
+        while extra + 4 <= extra_end {
+            let (id, size) = (u16_at(zip, extra), u16_at(zip, extra + 2) as usize);
+            // a size beyond the extra fields of the entry must not be read
+            let field_end = (extra + 4 + size).min(extra_end);
+            if id == 0x0001 {
+                let mut field = extra + 4;
+                for value in [&mut uncompressed, &mut compressed, &mut offset].iter_mut() {
+                    if **value == 0xFFFF_FFFF && field + 8 <= field_end {
+                        **value = u64_at(zip, field);
+                        field += 8;
+                    }
+                }
+            }
+            extra += 4 + size;
+        }
+
+        if method != 0 || compressed != uncompressed {
+            return Err(invalid_data(format!(
+                "{}: {} is compressed, only uncompressed archives are supported", path, name)));
+        }
+        let local = offset as usize;
+        if local.checked_add(30).map_or(true, |end| end > zip.len()) ||
+            u32_at(zip, local) != 0x0403_4b50 {
+            return Err(corrupt());
+        }
+        let start = local + 30 + u16_at(zip, local + 26) as usize
+            + u16_at(zip, local + 28) as usize;
+        let end = start.checked_add(compressed as usize).ok_or_else(corrupt)?;
+        if end > zip.len() { return Err(corrupt()); }
+        files.push((name, &zip[start..end]));
+
+        i += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(files)
+}
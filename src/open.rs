@@ -0,0 +1,214 @@
+//! # Open Borders
+//!
+//! a border at a fixed potential pulls the field of isolated electrodes towards it unless the
+//! domain is many times bigger than the electrodes. An open border instead takes its potential
+//! from a multipole expansion of the field outside of the electrodes: the expansion is fitted
+//! (least squares) to the potential on a contour a few nodes inside of the border, evaluated at
+//! the border nodes, and the grid is solved again with the new border until the border stops
+//! changing. In planar grids the expansion is `a_0 + sum (a_n cos nθ + b_n sin nθ) / ρ^n`, so the
+//! potential stays bounded far away (the electrodes carry no net charge) and `a_0` is the
+//! potential at infinity. In axisymmetric grids it is `sum a_l P_l(cos θ) / ρ^(l + 1)`, which
+//! vanishes at infinity.
+
+use super::Scale;
+use stencil::Geometry;
+
+/// A border that is open towards infinity, see `BoundaryCondition::Open`. Every fixed box and
+/// conductor has to be inside of the contour. Checkpoints keep the open border, grids read from
+/// other files have a closed border again
+///
+/// # Fields
+///
+/// * `center` - external coordinates of the center of the expansion, among the electrodes. The
+///   center of an axisymmetric grid is on the axis, only its y (z) coordinate is used
+/// * `order` - highest order of the multipoles in the expansion
+/// * `inset` - nodes between the border and the contour the expansion is fitted on, at least 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpenBoundary {
+    pub center: (f64, f64),
+    pub order: usize,
+    pub inset: usize,
+}
+
+impl OpenBoundary {
+    /// number of coefficients of the expansion
+    fn terms(&self, geometry: Geometry) -> usize {
+        match geometry {
+            Geometry::Planar => 2 * self.order + 1,
+            Geometry::Axisymmetric => self.order + 1,
+        }
+    }
+
+    /// The border nodes and the contour of a grid `width` x `height` nodes big, fails if the
+    /// contour does not enclose the center and all `electrode_nodes` or is too short for the
+    /// expansion
+    pub(crate) fn border(&self, scale: &Scale, geometry: Geometry, width: usize, height: usize,
+                         electrode_nodes: &[usize]) -> Result<OpenBorder, String> {
+        let axis = geometry.has_axis(scale);
+        let inset = self.inset;
+        if inset == 0 || width < 2 * inset + 3 || height < 2 * inset + 3 {
+            return Err(format!("the inset has to be at least 1 and the grid at least {} x {} \
+                nodes", 2 * inset + 3, 2 * inset + 3));
+        }
+        // strictly inside of the contour
+        let inside = |column: f64, row: f64| {
+            (axis || column > inset as f64) && column < (width - 1 - inset) as f64 &&
+                row > inset as f64 && row < (height - 1 - inset) as f64
+        };
+        if let Some(&i) = electrode_nodes.iter()
+            .find(|&&i| !inside((i % width) as f64, (i / width) as f64)) {
+            let (x, y) = scale.to_external((i % width) as f64, (i / width) as f64);
+            return Err(format!("the electrode node at ({}, {}) is not inside of the contour",
+                               x, y));
+        }
+        // the center of an axisymmetric grid only needs a row inside of the contour
+        let center_column = match geometry {
+            Geometry::Planar => scale.x.to_internal(self.center.0),
+            Geometry::Axisymmetric => (width / 2) as f64,
+        };
+        if !inside(center_column, scale.y.to_internal(self.center.1)) {
+            return Err(String::from("the center has to be inside of the contour"));
+        }
+
+        let ring = |distance: usize| {
+            let (left, right) = (if axis { 0 } else { distance }, width - 1 - distance);
+            let (top, bottom) = (distance, height - 1 - distance);
+            let mut nodes = Vec::new();
+            for row in top..bottom + 1 {
+                for column in left..right + 1 {
+                    if row == top || row == bottom || column == right ||
+                        (!axis && column == left) {
+                        nodes.push(row * width + column);
+                    }
+                }
+            }
+            nodes
+        };
+        let border = ring(0);
+        let contour = ring(inset);
+        if contour.len() < self.terms(geometry) {
+            return Err(format!("{} contour nodes are too few for multipoles up to order {}",
+                               contour.len(), self.order));
+        }
+        Ok(OpenBorder { settings: *self, border, contour })
+    }
+
+    /// the terms of the expansion at the external coordinates `x` and `y`, `radius` scales the
+    /// distance to the center
+    fn basis(&self, geometry: Geometry, x: f64, y: f64, radius: f64) -> Vec<f64> {
+        let mut terms = Vec::with_capacity(self.terms(geometry));
+        match geometry {
+            Geometry::Planar => {
+                let (d_x, d_y) = (x - self.center.0, y - self.center.1);
+                let (rho, theta) = (d_x.hypot(d_y), d_y.atan2(d_x));
+                terms.push(1.0);
+                for n in 1..self.order + 1 {
+                    let decay = (radius / rho).powi(n as i32);
+                    let angle = n as f64 * theta;
+                    terms.push(decay * angle.cos());
+                    terms.push(decay * angle.sin());
+                }
+            }
+            Geometry::Axisymmetric => {
+                let d_z = y - self.center.1;
+                let rho = x.hypot(d_z);
+                let cosine = d_z / rho;
+                // Legendre polynomials by their recurrence
+                let (mut previous, mut current) = (0.0, 1.0);
+                for l in 0..self.order + 1 {
+                    terms.push((radius / rho).powi(l as i32 + 1) * current);
+                    let next = ((2 * l + 1) as f64 * cosine * current - l as f64 * previous) /
+                        (l + 1) as f64;
+                    previous = current;
+                    current = next;
+                }
+            }
+        }
+        terms
+    }
+}
+
+/// An open border of a grid
+///
+/// * `settings` - how the expansion is made
+/// * `border` - indices of the border nodes the expansion sets
+/// * `contour` - indices of the nodes the expansion is fitted to
+#[derive(Debug, Clone)]
+pub(crate) struct OpenBorder {
+    pub settings: OpenBoundary,
+    pub border: Vec<usize>,
+    pub contour: Vec<usize>,
+}
+
+impl OpenBorder {
+    /// the potential of the expansion fitted to the contour of `nodes` at every border node
+    pub fn potentials(&self, nodes: &[f64], width: usize, scale: &Scale, geometry: Geometry)
+        -> Vec<f64> {
+        let settings = &self.settings;
+        let position = |i: usize| scale.to_external((i % width) as f64, (i / width) as f64);
+        let distance = |(x, y): (f64, f64)| match geometry {
+            Geometry::Planar => (x - settings.center.0).hypot(y - settings.center.1),
+            Geometry::Axisymmetric => x.hypot(y - settings.center.1),
+        };
+        let radius = self.contour.iter().map(|&i| distance(position(i))).sum::<f64>() /
+            self.contour.len() as f64;
+
+        // normal equations of the least squares fit
+        let terms = settings.terms(geometry);
+        let mut matrix = vec![vec![0.0; terms]; terms];
+        let mut right_side = vec![0.0; terms];
+        for &i in self.contour.iter() {
+            let (x, y) = position(i);
+            let basis = settings.basis(geometry, x, y, radius);
+            for (row, &a) in basis.iter().enumerate() {
+                for (column, &b) in basis.iter().enumerate() {
+                    matrix[row][column] += a * b;
+                }
+                right_side[row] += a * nodes[i];
+            }
+        }
+        let coefficients = solve_linear(matrix, right_side);
+
+        self.border.iter().map(|&i| {
+            let (x, y) = position(i);
+            settings.basis(geometry, x, y, radius).iter().zip(coefficients.iter())
+                .map(|(term, coefficient)| term * coefficient).sum()
+        }).collect()
+    }
+}
+
+/// Solution of the linear system `matrix` x = `right_side` by Gaussian elimination with partial
+/// pivoting, unknowns without a usable pivot are 0
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut right_side: Vec<f64>) -> Vec<f64> {
+    let size = right_side.len();
+    let scale = matrix.iter().flat_map(|row| row.iter()).fold(0.0f64, |a, &b| a.max(b.abs()));
+    let mut usable = vec![false; size];
+    for column in 0..size {
+        let pivot = (column..size).fold(column, |best, row|
+            if matrix[row][column].abs() > matrix[best][column].abs() { row } else { best });
+        matrix.swap(column, pivot);
+        right_side.swap(column, pivot);
+        if matrix[column][column].abs() <= scale * 1e-14 {
+            continue;
+        }
+        usable[column] = true;
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (entry, &pivot_entry) in row[column..].iter_mut().zip(pivot_row[column..].iter()) {
+                *entry -= factor * pivot_entry;
+            }
+            right_side[column + 1 + offset] -= factor * right_side[column];
+        }
+    }
+    let mut solution = vec![0.0; size];
+    for row in (0..size).rev() {
+        if !usable[row] {
+            continue;
+        }
+        let rest: f64 = (row + 1..size).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (right_side[row] - rest) / matrix[row][row];
+    }
+    solution
+}
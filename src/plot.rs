@@ -2,41 +2,68 @@ use std::fs::File;
 use std::process::Command;
 use std::io::Write;
 
+use units::Units;
+
 /// Writes a temporary gnuplot script file and then calls gnuplot on that file
 fn plot(script: &String) {
     let mut temp = File::create("temp").expect("Could not create temp file");
     temp.write_all(script.as_bytes()).expect("Could not write to temp file");
     Command::new("gnuplot")
-        .args(&["-p", "temp"])
+        .args(["-p", "temp"])
         .status()
         .expect("Failed to plot Data (Is gnuplot installed?)");
 }
 
-pub fn plot_2d_color_map(file: &str) {
+/// the labels name the units of `units`
+pub fn plot_2d_color_map(file: &str, units: Units) {
     plot(&format!(
         "set terminal pngcairo  transparent enhanced font \"arial,10\" fontscale 1.0 size 800, 600
 set output 'out.png'
 set title \"Electrical Potential Approximation Based On Laplace Equation\"
-set cblabel \"Potential in kV\"
+set xlabel \"x in {1}\"
+set ylabel \"y in {1}\"
+set cblabel \"Potential in {2}\"
 set datafile separator comma
 set autoscale xfix
 set autoscale yfix
-plot \"{}\" matrix nonuniform with image", file));
+plot \"{0}\" matrix nonuniform with image", file, units.length.symbol(),
+        units.potential.symbol()));
 }
 
-pub fn plot_lines_by_column(file: &str, title: &str, base: f64, step: f64) {
+pub fn plot_lines_by_column(file: &str, title: &str, base: f64, step: f64, units: Units) {
     plot(&format!(
         "set terminal pngcairo  transparent enhanced font \"arial,10\" fontscale 1.0 size 800, 600
 set output 'watch.png'
 set title \"{}\"
-set cblabel \"Potential in kV\"
+set cblabel \"Potential in {4}\"
 set datafile separator comma
 set autoscale xfix
 set autoscale yfix
 set xlabel \"Iterations\"
-set ylabel \"Potential in kV\"
+set ylabel \"Potential in {4}\"
 set key bmargin center horizontal Right noreverse enhanced autotitle box lt black linewidth 1.000\
  dashtype solid
 plot for [col=0:*] \"{}\" matrix using 1:0 every :::col::col with lines title sprintf(\"ω=%1.2f\",\
- col*{}+{})", title, file, step, base));
-}
\ No newline at end of file
+ col*{}+{})", title, file, step, base, units.potential.symbol()));
+}
+
+/// plots a profile written by `profile_to_csv`, the potential over the distance along the profile
+/// and the magnitude of the electric field on the second y axis
+pub fn plot_profile(file: &str, title: &str, units: Units) {
+    plot(&format!(
+        "set terminal pngcairo  transparent enhanced font \"arial,10\" fontscale 1.0 size 800, 600
+set output 'profile.png'
+set title \"{}\"
+set datafile separator comma
+set autoscale xfix
+set xlabel \"Distance in {3}\"
+set ylabel \"Potential in {4}\"
+set y2label \"Electric field in {5}\"
+set ytics nomirror
+set y2tics
+set key bmargin center horizontal Right noreverse enhanced autotitle box lt black linewidth 1.000\
+ dashtype solid
+plot \"{}\" using 1:4 skip 1 with lines title \"Potential\", \
+\"{}\" using 1:(sqrt($5**2+$6**2)) skip 1 with lines axes x1y2 title \"|E|\"", title, file, file,
+        units.length.symbol(), units.potential.symbol(), units.field_symbol()));
+}
@@ -0,0 +1,93 @@
+//! # Profiles
+//!
+//! extracting the potential and the electric field along straight lines or polylines (i.e. cross
+//! sections through the grid)
+
+use std::fs::File;
+use std::io::Write;
+
+use super::Grid;
+use sample::{Interpolation, SampleError};
+
+/// One point of a profile
+///
+/// # Fields
+///
+/// * `distance` - distance along the line (or polyline) from its first point
+/// * `x` - external coordinate of the sample
+/// * `y` - external coordinate of the sample
+/// * `potential` - interpolated potential
+/// * `field` - electric field `(E_x, E_y)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileSample {
+    pub distance: f64,
+    pub x: f64,
+    pub y: f64,
+    pub potential: f64,
+    pub field: (f64, f64),
+}
+
+impl Grid {
+    /// Samples `samples` evenly spaced points on the segment from `start` to `end` (both in
+    /// external coordinates and both included)
+    pub fn line_profile(&self, start: (f64, f64), end: (f64, f64), samples: usize,
+                        interpolation: Interpolation) -> Result<Vec<ProfileSample>, SampleError> {
+        self.polyline_profile(&[start, end], samples, interpolation)
+    }
+
+    /// Samples `samples` points evenly spaced (by distance) along the polyline through `points`.
+    /// The first and the last point are always part of the profile. Fewer than two points or
+    /// samples result in `SampleError::TooFewPoints`
+    pub fn polyline_profile(&self, points: &[(f64, f64)], samples: usize,
+                            interpolation: Interpolation)
+        -> Result<Vec<ProfileSample>, SampleError> {
+        if points.len() < 2 || samples < 2 {
+            return Err(SampleError::TooFewPoints { points: points.len(), samples });
+        }
+
+        let lengths: Vec<f64> = points.windows(2)
+            .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+            .collect();
+        let total_length: f64 = lengths.iter().sum();
+
+        let mut profile = Vec::with_capacity(samples);
+        let mut segment = 0;
+        let mut segment_start = 0.0;
+        for i in 0..samples {
+            let distance = total_length * i as f64 / (samples - 1) as f64;
+            // moving on to the segment containing `distance`
+            while segment < lengths.len() - 1 && distance > segment_start + lengths[segment] {
+                segment_start += lengths[segment];
+                segment += 1;
+            }
+            let t = if lengths[segment] > 0.0 {
+                ((distance - segment_start) / lengths[segment]).min(1.0)
+            } else { 0.0 };
+            let (a, b) = (points[segment], points[segment + 1]);
+            let (x, y) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            profile.push(ProfileSample {
+                distance,
+                x,
+                y,
+                potential: self.sample(x, y, interpolation)?,
+                field: self.sample_field(x, y, interpolation)?,
+            });
+        }
+        Ok(profile)
+    }
+}
+
+/// writes a profile to a csv file with one sample per row, the first row contains the column
+/// names (distance, x, y, potential, field_x, field_y)
+pub fn profile_to_csv(profile: &[ProfileSample], path: &str) -> std::io::Result<()> {
+    let mut csv = File::create(path)?;
+    let mut file_string = String::with_capacity((profile.len() + 1) * 60);
+
+    file_string.push_str("distance,x,y,potential,field_x,field_y\n");
+    for sample in profile.iter() {
+        file_string.push_str(&format!("{},{},{},{},{},{}\n", sample.distance, sample.x, sample.y,
+                                      sample.potential, sample.field.0, sample.field.1));
+    }
+    csv.write_all(file_string.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,381 @@
+//! # Rendering
+//!
+//! a small pure rust renderer for heat maps of the potential, so no gnuplot (and no temporary
+//! script file) is needed. The images can be saved as png or ppm.
+//! Png files are written uncompressed (stored deflate blocks), which keeps this free of
+//! dependencies at the cost of bigger files.
+
+use std::fs::File;
+use std::io::Write;
+
+use super::{box_extent, FixedBox, Grid};
+use checksum::{adler32, crc32};
+use sample::Interpolation;
+
+/// Colormaps available for heat maps
+///
+/// * `Viridis` - perceptually uniform, dark blue to yellow
+/// * `Inferno` - perceptually uniform, black to light yellow
+/// * `Diverging` - blue to grey to red, the grey is in the middle of the value range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Inferno,
+    Diverging,
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84], [71, 44, 122], [59, 81, 139], [44, 113, 142], [33, 144, 141],
+    [39, 173, 129], [92, 200, 99], [170, 220, 50], [253, 231, 37],
+];
+
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4], [31, 12, 72], [85, 15, 109], [136, 34, 106], [186, 54, 85],
+    [227, 89, 51], [249, 140, 10], [249, 201, 50], [252, 255, 164],
+];
+
+const DIVERGING: [[u8; 3]; 9] = [
+    [59, 76, 192], [98, 130, 234], [141, 176, 254], [184, 208, 249], [221, 221, 221],
+    [245, 196, 173], [244, 154, 123], [222, 96, 77], [180, 4, 38],
+];
+
+impl Colormap {
+    /// colour for `t` in [0, 1] (values outside are clamped)
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        let table = match *self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Diverging => &DIVERGING,
+        };
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let position = t * (table.len() - 1) as f64;
+        let i = (position.floor() as usize).min(table.len() - 2);
+        let f = position - i as f64;
+        let mut color = [0; 3];
+        for (c, value) in color.iter_mut().enumerate() {
+            *value = (table[i][c] as f64 * (1.0 - f) + table[i + 1][c] as f64 * f).round() as u8;
+        }
+        color
+    }
+}
+
+/// Settings for `Grid::heat_map`
+///
+/// # Fields
+///
+/// * `colormap` - colours used for the potential
+/// * `pixels_per_node` - size of one node in the image
+/// * `range` - potentials mapped to the ends of the colormap, if `None` the smallest and biggest
+///   node values are used
+/// * `colorbar` - draws a colourbar with labels right of the map
+/// * `axis_labels` - adds a margin with ticks and labels in external coordinates
+/// * `electrodes` - outlines of these boxes are drawn on top of the map
+pub struct HeatMap<'e> {
+    pub colormap: Colormap,
+    pub pixels_per_node: usize,
+    pub range: Option<(f64, f64)>,
+    pub colorbar: bool,
+    pub axis_labels: bool,
+    pub electrodes: &'e [FixedBox],
+}
+
+impl<'e> HeatMap<'e> {
+    /// heat map with colourbar and axis labels but no electrodes
+    pub fn new(colormap: Colormap) -> HeatMap<'e> {
+        HeatMap {
+            colormap,
+            pixels_per_node: 2,
+            range: None,
+            colorbar: true,
+            axis_labels: true,
+            electrodes: &[],
+        }
+    }
+}
+
+/// A plain rgb image
+///
+/// # Fields
+///
+/// * `width` - width in pixels
+/// * `height` - height in pixels
+/// * `pixels` - three bytes (red, green, blue) per pixel, row by row starting at the top
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const FOREGROUND: [u8; 3] = [0, 0, 0];
+const ELECTRODE: [u8; 3] = [255, 255, 255];
+/// size of the margin at each side of the map, the left and bottom one hold the axis labels
+const MARGIN: usize = 8;
+const LABEL_MARGIN: usize = 56;
+const COLORBAR_WIDTH: usize = 16;
+const TICK_LENGTH: usize = 4;
+/// the font is 3x5 pixels and drawn with this factor
+const FONT_SCALE: usize = 2;
+
+impl Image {
+    fn new(width: usize, height: usize) -> Image {
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for _ in 0..width * height {
+            pixels.extend_from_slice(&BACKGROUND);
+        }
+        Image { width, height, pixels }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let i = (y * self.width + x) * 3;
+            self.pixels[i..i + 3].copy_from_slice(&color);
+        }
+    }
+
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn outline_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        if width == 0 || height == 0 { return; }
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, y + height - 1, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(x + width - 1, y, 1, height, color);
+    }
+
+    /// width of `text` in pixels when drawn with `draw_text`
+    fn text_width(text: &str) -> usize {
+        text.chars().count() * 4 * FONT_SCALE
+    }
+
+    fn draw_text(&mut self, x: usize, y: usize, text: &str, color: [u8; 3]) {
+        for (n, c) in text.chars().enumerate() {
+            let glyph = glyph(c);
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.fill_rect(x + (n * 4 + column) * FONT_SCALE, y + row * FONT_SCALE,
+                                       FONT_SCALE, FONT_SCALE, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// writes the image as a binary ppm (P6) file
+    pub fn save_ppm(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(format!("P6\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        file.write_all(&self.pixels)?;
+        Ok(())
+    }
+
+    /// writes the image as an (uncompressed) png file
+    pub fn save_png(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&self.encode_png())?;
+        Ok(())
+    }
+
+    fn encode_png(&self) -> Vec<u8> {
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bit depth, rgb, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+
+        // every row starts with its filter type (0 = none)
+        let row_length = self.width * 3;
+        let mut raw = Vec::with_capacity((row_length + 1) * self.height);
+        for row in self.pixels.chunks(row_length) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        // zlib stream made of stored (uncompressed) deflate blocks
+        let mut zlib = vec![0x78, 0x01];
+        let blocks = raw.chunks(0xFFFF).count();
+        for (i, block) in raw.chunks(0xFFFF).enumerate() {
+            zlib.push(if i + 1 == blocks { 1 } else { 0 });
+            zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+        write_chunk(&mut png, b"IDAT", &zlib);
+
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+impl Grid {
+    /// Renders the potential as a heat map. The map is oriented like a plot, x grows to the right
+    /// and y upwards no matter how the internal axes are inverted
+    pub fn heat_map(&self, settings: &HeatMap) -> Image {
+        let height = self.height();
+        let ppn = settings.pixels_per_node.max(1);
+        let (map_width, map_height) = (self.width * ppn, height * ppn);
+        let (min, max) = settings.range.unwrap_or_else(|| {
+            self.nodes.iter().fold((f64::INFINITY, f64::NEG_INFINITY),
+                                   |(min, max), &v| (min.min(v), max.max(v)))
+        });
+        let span = if max > min { max - min } else { 1.0 };
+
+        let left = if settings.axis_labels { LABEL_MARGIN } else { MARGIN };
+        let bottom = if settings.axis_labels { LABEL_MARGIN / 2 } else { MARGIN };
+        let right = if settings.colorbar { MARGIN * 2 + COLORBAR_WIDTH + LABEL_MARGIN }
+            else { MARGIN };
+        let mut image = Image::new(left + map_width + right, MARGIN + map_height + bottom);
+
+        // the map is evenly spaced in external coordinates (`u` and `v` are in mean node
+        // spacings from its left and top edge), every pixel is mapped back to the internal
+        // coordinates, which are not evenly spaced on graded axes
+        let (x_a, y_a) = self.scale.to_external(0.0, 0.0);
+        let (x_b, y_b) = self.scale.to_external((self.width - 1) as f64, (height - 1) as f64);
+        let (x_start, x_end, y_start, y_end) = (x_a.min(x_b), x_a.max(x_b), y_a.min(y_b),
+                                                y_a.max(y_b));
+        let x_step = (x_end - x_start) / (self.width - 1).max(1) as f64;
+        let y_step = (y_end - y_start) / (height - 1).max(1) as f64;
+        let u_of = |x: f64| if x_step > 0.0 { (x - x_start) / x_step } else { 0.0 };
+        let v_of = |y: f64| if y_step > 0.0 { (y_end - y) / y_step } else { 0.0 };
+        for py in 0..map_height {
+            let v = ((py as f64 + 0.5) / ppn as f64 - 0.5).max(0.0).min((height - 1) as f64);
+            let row = self.scale.y.to_internal(y_end - v * y_step).max(0.0)
+                .min((height - 1) as f64);
+            for px in 0..map_width {
+                let u = ((px as f64 + 0.5) / ppn as f64 - 0.5).max(0.0)
+                    .min((self.width - 1) as f64);
+                let column = self.scale.x.to_internal(x_start + u * x_step).max(0.0)
+                    .min((self.width - 1) as f64);
+                let value = self.sample_internal(column, row, Interpolation::Bilinear);
+                image.set(left + px, MARGIN + py, settings.colormap.color((value - min) / span));
+            }
+        }
+
+        // electrode outlines
+        for fixed_box in settings.electrodes.iter() {
+            let (columns, rows) = box_extent(fixed_box, &self.scale);
+            if columns.1 <= columns.0 || rows.1 <= rows.0 { continue; }
+            // from the first to the last covered node, half a pixel block beyond both of them
+            let (x_0, y_0) = self.scale.to_external(columns.0 as f64, rows.0 as f64);
+            let (x_1, y_1) = self.scale.to_external((columns.1 - 1) as f64, (rows.1 - 1) as f64);
+            let (u0, u1) = (u_of(x_0).min(u_of(x_1)), u_of(x_0).max(u_of(x_1)) + 1.0);
+            let (v0, v1) = (v_of(y_0).min(v_of(y_1)), v_of(y_0).max(v_of(y_1)) + 1.0);
+            let to_pixels = |a: f64, b: f64, limit: usize| {
+                let a = (a.max(0.0) * ppn as f64) as usize;
+                let b = ((b * ppn as f64).max(0.0) as usize).min(limit);
+                (a, b.saturating_sub(a))
+            };
+            let (x, w) = to_pixels(u0, u1, map_width);
+            let (y, h) = to_pixels(v0, v1, map_height);
+            image.outline_rect(left + x, MARGIN + y, w, h, ELECTRODE);
+        }
+
+        if settings.axis_labels {
+            image.outline_rect(left - 1, MARGIN - 1, map_width + 2, map_height + 2, FOREGROUND);
+            for tick in nice_ticks(x_start, x_end) {
+                let px = left + ((u_of(tick) + 0.5) * ppn as f64) as usize;
+                let label = format_tick(tick, x_start, x_end);
+                image.fill_rect(px, MARGIN + map_height, 1, TICK_LENGTH, FOREGROUND);
+                image.draw_text(px.saturating_sub(Image::text_width(&label) / 2),
+                                MARGIN + map_height + TICK_LENGTH * 2, &label, FOREGROUND);
+            }
+            for tick in nice_ticks(y_start, y_end) {
+                let py = MARGIN + map_height - 1 -
+                    (((height - 1) as f64 - v_of(tick) + 0.5) * ppn as f64) as usize;
+                let label = format_tick(tick, y_start, y_end);
+                image.fill_rect(left - TICK_LENGTH, py, TICK_LENGTH, 1, FOREGROUND);
+                image.draw_text(
+                    (left - TICK_LENGTH * 2).saturating_sub(Image::text_width(&label)),
+                    py.saturating_sub(5 * FONT_SCALE / 2), &label, FOREGROUND);
+            }
+        }
+
+        if settings.colorbar {
+            let x = left + map_width + MARGIN * 2;
+            for py in 0..map_height {
+                let t = 1.0 - py as f64 / (map_height - 1).max(1) as f64;
+                image.fill_rect(x, MARGIN + py, COLORBAR_WIDTH, 1, settings.colormap.color(t));
+            }
+            image.outline_rect(x - 1, MARGIN - 1, COLORBAR_WIDTH + 2, map_height + 2, FOREGROUND);
+            if max > min {
+                for tick in nice_ticks(min, max) {
+                    let py = MARGIN + map_height - 1 -
+                        ((tick - min) / span * (map_height - 1) as f64) as usize;
+                    let label = format_tick(tick, min, max);
+                    image.fill_rect(x + COLORBAR_WIDTH, py, TICK_LENGTH, 1, FOREGROUND);
+                    image.draw_text(x + COLORBAR_WIDTH + TICK_LENGTH * 2,
+                                    py.saturating_sub(5 * FONT_SCALE / 2), &label, FOREGROUND);
+                }
+            }
+        }
+        image
+    }
+}
+
+/// about five evenly spaced "round" values (multiples of 1, 2 or 5 times a power of ten) between
+/// `min` and `max`
+pub(crate) fn nice_ticks(min: f64, max: f64) -> Vec<f64> {
+    if max <= min || !min.is_finite() || !max.is_finite() { return vec![]; }
+    let step = tick_step(min, max);
+    let mut ticks = Vec::new();
+    let mut tick = (min / step).ceil() * step;
+    while tick <= max + step * 1e-9 {
+        ticks.push(if tick.abs() < step * 1e-9 { 0.0 } else { tick });
+        tick += step;
+    }
+    ticks
+}
+
+fn tick_step(min: f64, max: f64) -> f64 {
+    let rough = (max - min) / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let normalized = rough / magnitude;
+    magnitude * if normalized < 1.5 { 1.0 } else if normalized < 3.5 { 2.0 }
+        else if normalized < 7.5 { 5.0 } else { 10.0 }
+}
+
+/// formats a tick with as many decimals as the tick step needs
+pub(crate) fn format_tick(tick: f64, min: f64, max: f64) -> String {
+    let decimals = (-tick_step(min, max).log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, tick)
+}
+
+/// 3x5 pixel glyphs, every byte is one row with the three lowest bits as pixels
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        'e' => [0b000, 0b011, 0b111, 0b100, 0b011],
+        _ => [0b000; 5],
+    }
+}
@@ -0,0 +1,128 @@
+//! # Sampling
+//!
+//! reading the potential of a grid at arbitrary (real) external coordinates. The nodes are
+//! interpolated either bilinearly or bicubically (Catmull-Rom)
+
+use std::error::Error;
+use std::fmt;
+
+use super::Grid;
+
+/// Method used to interpolate between the nodes of a grid
+///
+/// * `Bilinear` - uses the four surrounding nodes, continuous but with kinks at node lines
+/// * `Bicubic` - uses the surrounding 4x4 nodes (Catmull-Rom), smooth first derivative
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Bilinear,
+    Bicubic,
+}
+
+/// Everything that can go wrong while sampling a grid
+#[derive(Debug, Clone, PartialEq)]
+pub enum SampleError {
+    /// the requested point (in external coordinates) does not lie inside of the grid
+    OutsideDomain { x: f64, y: f64 },
+    /// a profile got fewer than two `points` or `samples`
+    TooFewPoints { points: usize, samples: usize },
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SampleError::OutsideDomain { x, y } =>
+                write!(f, "({}, {}) is outside of the grid", x, y),
+            SampleError::TooFewPoints { points, samples } =>
+                write!(f, "a profile needs at least two points and two samples, not {} and {}",
+                       points, samples),
+        }
+    }
+}
+
+impl Error for SampleError {}
+
+/// points closer than this (in nodes) to the edge of the grid are still accepted, otherwise
+/// coordinates on the border might get rejected because of rounding errors
+const EDGE_TOLERANCE: f64 = 1e-9;
+
+impl Grid {
+    /// Returns the potential at the external coordinates `x` and `y` which do not have to lie on a
+    /// node. Points outside of the grid result in `SampleError::OutsideDomain`
+    pub fn sample(&self, x: f64, y: f64, interpolation: Interpolation)
+        -> Result<f64, SampleError> {
+        let (column, row) = self.internal_position(x, y)?;
+        Ok(self.sample_internal(column, row, interpolation))
+    }
+
+    /// same as `sample` but with internal coordinates that have to be inside the grid already
+    pub(crate) fn sample_internal(&self, column: f64, row: f64, interpolation: Interpolation)
+        -> f64 {
+        match interpolation {
+            Interpolation::Bilinear => self.bilinear(column, row),
+            Interpolation::Bicubic => self.bicubic(column, row),
+        }
+    }
+
+    /// same as `sample` but points outside of the grid are moved onto its border instead of
+    /// failing
+    pub(crate) fn sample_clamped(&self, x: f64, y: f64, interpolation: Interpolation) -> f64 {
+        let (column, row) = self.scale.to_internal(x, y);
+        let column = column.max(0.0).min((self.width - 1) as f64);
+        let row = row.max(0.0).min((self.height() - 1) as f64);
+        self.sample_internal(column, row, interpolation)
+    }
+
+    /// Translates external coordinates to internal ones and checks that they are inside the grid.
+    /// Points just outside (because of rounding) are clamped onto the border
+    pub(crate) fn internal_position(&self, x: f64, y: f64) -> Result<(f64, f64), SampleError> {
+        let (column, row) = self.scale.to_internal(x, y);
+        let max_column = (self.width - 1) as f64;
+        let max_row = (self.height() - 1) as f64;
+        if !(column >= -EDGE_TOLERANCE && column <= max_column + EDGE_TOLERANCE &&
+            row >= -EDGE_TOLERANCE && row <= max_row + EDGE_TOLERANCE) {
+            return Err(SampleError::OutsideDomain { x, y });
+        }
+        Ok((column.max(0.0).min(max_column), row.max(0.0).min(max_row)))
+    }
+
+    /// node value with the indices clamped to the grid, used for the stencils of the interpolation
+    fn clamped_node(&self, column: isize, row: isize) -> f64 {
+        let column = column.max(0).min(self.width as isize - 1) as usize;
+        let row = row.max(0).min(self.height() as isize - 1) as usize;
+        self.nodes[row * self.width + column]
+    }
+
+    fn bilinear(&self, column: f64, row: f64) -> f64 {
+        let (left, top) = (column.floor() as isize, row.floor() as isize);
+        let (tx, ty) = (column - left as f64, row - top as f64);
+        let upper = self.clamped_node(left, top) * (1.0 - tx)
+            + self.clamped_node(left + 1, top) * tx;
+        let lower = self.clamped_node(left, top + 1) * (1.0 - tx)
+            + self.clamped_node(left + 1, top + 1) * tx;
+        upper * (1.0 - ty) + lower * ty
+    }
+
+    fn bicubic(&self, column: f64, row: f64) -> f64 {
+        let (left, top) = (column.floor() as isize, row.floor() as isize);
+        let (tx, ty) = (column - left as f64, row - top as f64);
+        let mut rows = [0.0; 4];
+        for (i, value) in rows.iter_mut().enumerate() {
+            let y = top - 1 + i as isize;
+            *value = catmull_rom([
+                self.clamped_node(left - 1, y),
+                self.clamped_node(left, y),
+                self.clamped_node(left + 1, y),
+                self.clamped_node(left + 2, y),
+            ], tx);
+        }
+        catmull_rom(rows, ty)
+    }
+}
+
+/// cubic interpolation between `p[1]` and `p[2]` with `t` in [0, 1]
+fn catmull_rom(p: [f64; 4], t: f64) -> f64 {
+    0.5 * (2.0 * p[1]
+        + (p[2] - p[0]) * t
+        + (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3]) * t * t
+        + (3.0 * p[1] - p[0] - 3.0 * p[2] + p[3]) * t * t * t)
+}
@@ -0,0 +1,265 @@
+//! # Stencil
+//!
+//! the 5-point finite difference stencil of every dynamic node. With unequal distances to the
+//! neighbours (graded axes, different x and y spacing) the second derivative along an axis is
+//! `2 / (h_a + h_b) * ((u_a - u) / h_a + (u_b - u) / h_b)`, solving the discrete Laplace equation
+//! for `u` gives a weighted mean of the four neighbours. The weights only depend on the scale and
+//! the geometry, so they are computed once when the grid is created.
+//!
+//! In axisymmetric grids the x axis is the radius r and the y axis is z. The Laplacian gains the
+//! term `1/r ∂u/∂r`, it is discretized as `1/r ∂/∂r (r ∂u/∂r)` with r taken halfway between
+//! the nodes, which weighs the outer neighbour more than the inner one. On the axis itself the
+//! symmetry `u(-r) = u(r)` turns the radial part into `4 (u_right - u) / h²`.
+//!
+//! The 9-point (Mehrstellen) stencil adds `(h_x² + h_y²) / 12 δ_x² δ_y² u` to the 5-point
+//! Laplacian, for harmonic potentials this cancels the leading error term and makes it fourth
+//! order accurate. It needs evenly spaced axes and planar geometry, and the spacings may differ by
+//! less than a factor of √5 (otherwise the weights of the side neighbours become negative).
+//!
+//! Next to a conductor the arm to a neighbour inside of it ends at its boundary (Shortley–Weller),
+//! the unequal distances are handled like the ones of graded axes. Such nodes always use the
+//! 5-point stencil.
+//!
+//! A source `f` turns the Laplace equation into the Poisson equation `-∇²u = f`. Since the
+//! Laplacian at a node is `diagonal * (mean - u)`, the node relaxes to the weighted mean plus
+//! `f / diagonal`. The 9-point stencil does not correct the source, with one it is only second
+//! order accurate.
+
+use super::Scale;
+use conductor::{arms, Conductor};
+use current::{Conductances, Conduction};
+use thread_pool::WeightedMean;
+
+/// What the two axes of a grid describe
+///
+/// * `Planar` - x and y of a cross section through a long, uniform part (the default)
+/// * `Axisymmetric` - radius (x) and z of a rotationally symmetric part. Fixed boxes are rings
+///   (discs if they touch the axis). The radius must not be negative and the x axis not
+///   inverted; if the first column is at r = 0 it is the axis, whose nodes are dynamic unless a
+///   fixed box covers them
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Geometry {
+    #[default]
+    Planar,
+    Axisymmetric,
+}
+
+impl Geometry {
+    /// whether the first column of a grid with this `scale` lies on the axis (and so is not
+    /// part of the border)
+    pub(crate) fn has_axis(&self, scale: &Scale) -> bool {
+        *self == Geometry::Axisymmetric && scale.x.origin.abs() <= scale.x.spacing * 1e-6
+    }
+}
+
+/// Which neighbours the Laplacian is discretized with
+///
+/// * `FivePoint` - the four side neighbours, second order accurate (the default)
+/// * `NinePoint` - the side and the diagonal neighbours (Mehrstellen), fourth order accurate in
+///   smooth regions so coarser grids reach the same accuracy
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StencilKind {
+    #[default]
+    FivePoint,
+    NinePoint,
+}
+
+impl StencilKind {
+    /// whether the stencil can be used with `scale` and `geometry`, the reason if not
+    pub(crate) fn check(&self, scale: &Scale, geometry: Geometry) -> Result<(), String> {
+        if *self == StencilKind::FivePoint {
+            return Ok(());
+        }
+        if scale.x.is_graded() || scale.y.is_graded() || geometry != Geometry::Planar {
+            return Err(String::from(
+                "the 9-point stencil needs evenly spaced axes and a planar geometry"));
+        }
+        let (h_x, h_y) = (scale.x.spacing, scale.y.spacing);
+        if h_x * h_x >= 5.0 * h_y * h_y || h_y * h_y >= 5.0 * h_x * h_x {
+            return Err(format!("the spacings {} and {} differ too much for the 9-point stencil",
+                               h_x, h_y));
+        }
+        Ok(())
+    }
+}
+
+/// Everything besides the scale the stencils of a grid depend on
+///
+/// * `geometry` - planar or axisymmetric
+/// * `stencil_kind` - 5-point or 9-point
+/// * `conductors` - curved or tilted electrodes whose boundaries shorten the arms of the stencils
+/// * `conduction` - conductivities that weigh the neighbours, for current flow
+/// * `source` - the right side of the Poisson equation at every node, empty without a source
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Discretization {
+    pub geometry: Geometry,
+    pub stencil_kind: StencilKind,
+    pub conductors: Vec<Conductor>,
+    pub conduction: Option<Conduction>,
+    pub source: Vec<f64>,
+}
+
+impl Discretization {
+    /// whether the border nodes may be dynamic (an insulating border)
+    pub fn dynamic_border(&self) -> bool {
+        self.conduction.as_ref().map_or(false, |conduction| conduction.insulating_border)
+    }
+}
+
+/// Weights of the neighbours of one dynamic node, they add up to 1. `corners` is the weight of
+/// each diagonal neighbour, it is only used by the 9-point stencil. `diagonal` is the sum of the
+/// weights before they were scaled to 1, the Laplacian at the node is `diagonal * (mean - u)`.
+/// `source` is the source at the node divided by `diagonal`, it is part of the mean
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Stencil {
+    pub left: f64,
+    pub right: f64,
+    pub top: f64,
+    pub bottom: f64,
+    pub corners: f64,
+    pub diagonal: f64,
+    pub source: f64,
+}
+
+impl Stencil {
+    /// the stencil of the node in `column` and `row`, it must not be on the border. `arms` are
+    /// the distances to the left, right, top and bottom neighbour as fractions of the node
+    /// spacing
+    fn at(column: usize, row: usize, scale: &Scale, geometry: Geometry, arms: [f64; 4])
+        -> Stencil {
+        let (h_top, h_bottom) = (scale.y.node_spacing(row - 1) * arms[2],
+                                 scale.y.node_spacing(row) * arms[3]);
+        let top = 2.0 / (h_top * (h_top + h_bottom));
+        let bottom = 2.0 / (h_bottom * (h_top + h_bottom));
+        let (left, right) = if column == 0 {
+            // on the axis, the mirrored right neighbour is the left one
+            let h_right = scale.x.node_spacing(0) * arms[1];
+            (0.0, 4.0 / (h_right * h_right))
+        } else {
+            let (h_left, h_right) = (scale.x.node_spacing(column - 1) * arms[0],
+                                     scale.x.node_spacing(column) * arms[1]);
+            let left = 2.0 / (h_left * (h_left + h_right));
+            let right = 2.0 / (h_right * (h_left + h_right));
+            match geometry {
+                Geometry::Planar => (left, right),
+                Geometry::Axisymmetric => {
+                    let r = scale.x.to_external(column as f64);
+                    (left * (r - h_left / 2.0) / r, right * (r + h_right / 2.0) / r)
+                }
+            }
+        };
+        let sum = left + right + top + bottom;
+        Stencil { left: left / sum, right: right / sum, top: top / sum, bottom: bottom / sum,
+                  corners: 0.0, diagonal: sum, source: 0.0 }
+    }
+
+    /// the 9-point stencil, the same for every node since the axes are evenly spaced
+    fn nine_point(scale: &Scale) -> Stencil {
+        let (h_x, h_y) = (scale.x.spacing * scale.x.spacing, scale.y.spacing * scale.y.spacing);
+        let corners = (h_x + h_y) / (12.0 * h_x * h_y);
+        let (sides_x, sides_y) = (1.0 / h_x - 2.0 * corners, 1.0 / h_y - 2.0 * corners);
+        let sum = 2.0 * sides_x + 2.0 * sides_y + 4.0 * corners;
+        Stencil { left: sides_x / sum, right: sides_x / sum, top: sides_y / sum,
+                  bottom: sides_y / sum, corners: corners / sum, diagonal: sum, source: 0.0 }
+    }
+
+    /// the stencils of all `dynamic_nodes` of a grid `width` x `height` nodes big
+    pub fn for_nodes(dynamic_nodes: &[usize], width: usize, height: usize, scale: &Scale,
+                     discretization: &Discretization) -> Vec<Stencil> {
+        let mut stencils = Stencil::weights(dynamic_nodes, width, height, scale, discretization);
+        if !discretization.source.is_empty() {
+            // nodes with a diagonal of 0 are fixed by `Grid::assemble`
+            for (stencil, &i) in stencils.iter_mut().zip(dynamic_nodes.iter()) {
+                if stencil.diagonal != 0.0 {
+                    stencil.source = discretization.source[i] / stencil.diagonal;
+                }
+            }
+        }
+        stencils
+    }
+
+    /// `for_nodes` without the source
+    fn weights(dynamic_nodes: &[usize], width: usize, height: usize, scale: &Scale,
+               discretization: &Discretization) -> Vec<Stencil> {
+        if let Some(ref conduction) = discretization.conduction {
+            let conductances = Conductances::new(conduction, scale, width, height);
+            return dynamic_nodes.iter().map(|&i| conductances.stencil(i)).collect();
+        }
+        let arms = if discretization.conductors.is_empty() {
+            vec![None; dynamic_nodes.len()]
+        } else {
+            arms(&discretization.conductors, scale, dynamic_nodes, width, height)
+        };
+        let geometry = discretization.geometry;
+        dynamic_nodes.iter().zip(arms).map(|(&i, arms)| {
+            match (discretization.stencil_kind, arms) {
+                (StencilKind::NinePoint, None) => Stencil::nine_point(scale),
+                (_, arms) => Stencil::at(i % width, i / width, scale, geometry,
+                                         arms.unwrap_or([1.0; 4])),
+            }
+        }).collect()
+    }
+
+    /// the weighted mean of the neighbours of node `i` (with the source)
+    #[inline]
+    pub fn apply(&self, nodes: &[f64], i: usize, width: usize) -> f64 {
+        nodes[i - 1] * self.left + nodes[i + 1] * self.right
+            + nodes[i - width] * self.top + nodes[i + width] * self.bottom + self.source
+    }
+
+    /// `apply` for nodes that may be on the border, neighbours outside of the grid count as 0
+    /// (their weight is 0 anyway)
+    #[inline]
+    pub fn apply_border(&self, nodes: &[f64], i: usize, width: usize) -> f64 {
+        let at = |j: Option<usize>| j.and_then(|j| nodes.get(j)).copied().unwrap_or(0.0);
+        at(i.checked_sub(1)) * self.left + at(Some(i + 1)) * self.right
+            + at(i.checked_sub(width)) * self.top + at(Some(i + width)) * self.bottom
+            + self.source
+    }
+
+    /// `apply` including the diagonal neighbours
+    #[inline]
+    pub fn apply_nine(&self, nodes: &[f64], i: usize, width: usize) -> f64 {
+        self.apply(nodes, i, width) + (nodes[i - width - 1] + nodes[i - width + 1]
+            + nodes[i + width - 1] + nodes[i + width + 1]) * self.corners
+    }
+}
+
+/// the stencils of the dynamic nodes of a grid `width` nodes wide, as the thread pool uses them
+pub(crate) struct Stencils<'s> {
+    pub stencils: &'s [Stencil],
+    pub width: usize,
+}
+
+impl<'s> WeightedMean for Stencils<'s> {
+    #[inline]
+    fn mean(&self, nodes: &[f64], k: usize, i: usize) -> f64 {
+        self.stencils[k].apply(nodes, i, self.width)
+    }
+}
+
+/// `Stencils` of a grid with dynamic border nodes
+pub(crate) struct BorderStencils<'s> {
+    pub stencils: &'s [Stencil],
+    pub width: usize,
+}
+
+impl<'s> WeightedMean for BorderStencils<'s> {
+    #[inline]
+    fn mean(&self, nodes: &[f64], k: usize, i: usize) -> f64 {
+        self.stencils[k].apply_border(nodes, i, self.width)
+    }
+}
+
+/// `Stencils` with the diagonal neighbours
+pub(crate) struct NinePointStencils<'s> {
+    pub stencils: &'s [Stencil],
+    pub width: usize,
+}
+
+impl<'s> WeightedMean for NinePointStencils<'s> {
+    #[inline]
+    fn mean(&self, nodes: &[f64], k: usize, i: usize) -> f64 {
+        self.stencils[k].apply_nine(nodes, i, self.width)
+    }
+}
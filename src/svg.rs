@@ -0,0 +1,272 @@
+//! # SVG Export
+//!
+//! vector graphics of a grid for reports: equipotential lines (marching squares), the outlines of
+//! the electrodes, a downsampled quiver plot of the electric field and axes in external
+//! coordinates
+
+use std::fs::File;
+use std::io::Write;
+
+use super::{box_extent, FixedBox, Grid};
+use render::{Colormap, nice_ticks, format_tick};
+
+/// Settings for `Grid::to_svg`
+///
+/// # Fields
+///
+/// * `units_to_pixels` - size of one unit of the external coordinate system in the svg
+/// * `contour_levels` - number of evenly spaced equipotential lines between the smallest and the
+///   biggest potential
+/// * `colormap` - colours of the equipotential lines
+/// * `arrow_spacing` - one field arrow is drawn every `arrow_spacing` nodes in both directions,
+///   0 disables the arrows
+/// * `electrodes` - these boxes are drawn as outlines
+/// * `axis_labels` - draws a frame with ticks and labels in external coordinates
+pub struct SvgPlot<'e> {
+    pub units_to_pixels: f64,
+    pub contour_levels: usize,
+    pub colormap: Colormap,
+    pub arrow_spacing: usize,
+    pub electrodes: &'e [FixedBox],
+    pub axis_labels: bool,
+}
+
+impl<'e> SvgPlot<'e> {
+    /// 20 equipotential lines, arrows every 10 nodes and axes but no electrodes
+    pub fn new() -> SvgPlot<'e> {
+        SvgPlot {
+            units_to_pixels: 10.0,
+            contour_levels: 20,
+            colormap: Colormap::Viridis,
+            arrow_spacing: 10,
+            electrodes: &[],
+            axis_labels: true,
+        }
+    }
+}
+
+impl<'e> Default for SvgPlot<'e> {
+    fn default() -> SvgPlot<'e> {
+        SvgPlot::new()
+    }
+}
+
+/// space around the plot, the left and bottom margins hold the labels
+const MARGIN: f64 = 10.0;
+const LABEL_MARGIN: f64 = 50.0;
+const TICK_LENGTH: f64 = 5.0;
+
+/// maps external coordinates to svg coordinates (y grows downwards in svg)
+struct Frame {
+    x_min: f64,
+    y_max: f64,
+    scale: f64,
+    left: f64,
+    top: f64,
+}
+
+impl Frame {
+    fn point(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.left + (x - self.x_min) * self.scale, self.top + (self.y_max - y) * self.scale)
+    }
+}
+
+impl Grid {
+    /// writes the grid as a svg file at `path`
+    pub fn to_svg(&self, path: &str, settings: &SvgPlot) -> std::io::Result<()> {
+        let mut svg = File::create(path)?;
+        svg.write_all(self.svg_string(settings).as_bytes())?;
+        Ok(())
+    }
+
+    fn svg_string(&self, settings: &SvgPlot) -> String {
+        let height = self.height();
+        let (x_a, y_a) = self.scale.to_external(0.0, 0.0);
+        let (x_b, y_b) = self.scale.to_external((self.width - 1) as f64, (height - 1) as f64);
+        let (x_min, x_max) = (x_a.min(x_b), x_a.max(x_b));
+        let (y_min, y_max) = (y_a.min(y_b), y_a.max(y_b));
+
+        let left = if settings.axis_labels { LABEL_MARGIN } else { MARGIN };
+        let bottom = if settings.axis_labels { LABEL_MARGIN } else { MARGIN };
+        let frame = Frame { x_min, y_max, scale: settings.units_to_pixels, left, top: MARGIN };
+        let plot_width = (x_max - x_min) * frame.scale;
+        let plot_height = (y_max - y_min) * frame.scale;
+
+        let mut svg = String::with_capacity(self.nodes.len() * 4);
+        svg.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\">\n",
+            left + plot_width + MARGIN, MARGIN + plot_height + bottom));
+        svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+
+        self.svg_contours(&mut svg, &frame, settings);
+        self.svg_arrows(&mut svg, &frame, settings);
+
+        for fixed_box in settings.electrodes.iter() {
+            let (columns, rows) = box_extent(fixed_box, &self.scale);
+            let (x, y) = self.scale.to_external(columns.0 as f64, rows.0 as f64);
+            let (x_a, y_a) = frame.point(x, y);
+            let (x, y) = self.scale.to_external(columns.1 as f64, rows.1 as f64);
+            let (x_b, y_b) = frame.point(x, y);
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                 stroke=\"black\" stroke-width=\"1.5\"/>\n",
+                x_a.min(x_b), y_a.min(y_b), (x_b - x_a).abs(), (y_b - y_a).abs()));
+        }
+
+        if settings.axis_labels {
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                 stroke=\"black\"/>\n", left, MARGIN, plot_width, plot_height));
+            svg.push_str("<g font-family=\"sans-serif\" font-size=\"10\">\n");
+            for tick in nice_ticks(x_min, x_max) {
+                let (x, y) = frame.point(tick, y_min);
+                svg.push_str(&format!(
+                    "<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"black\"/>\n\
+                     <text x=\"{0}\" y=\"{3}\" text-anchor=\"middle\">{4}</text>\n",
+                    x, y, y + TICK_LENGTH, y + TICK_LENGTH + 12.0,
+                    format_tick(tick, x_min, x_max)));
+            }
+            for tick in nice_ticks(y_min, y_max) {
+                let (x, y) = frame.point(x_min, tick);
+                svg.push_str(&format!(
+                    "<line x1=\"{0}\" y1=\"{1}\" x2=\"{2}\" y2=\"{1}\" stroke=\"black\"/>\n\
+                     <text x=\"{3}\" y=\"{4}\" text-anchor=\"end\">{5}</text>\n",
+                    x, y, x - TICK_LENGTH, x - TICK_LENGTH - 2.0, y + 3.5,
+                    format_tick(tick, y_min, y_max)));
+            }
+            let unit = self.scale.units.length.symbol();
+            svg.push_str(&format!(
+                "<text x=\"{0}\" y=\"{1}\" text-anchor=\"middle\">x in {2}</text>\n\
+                 <text x=\"{3}\" y=\"{4}\" text-anchor=\"middle\" \
+                 transform=\"rotate(-90 {3} {4})\">y in {2}</text>\n</g>\n",
+                left + plot_width / 2.0, MARGIN + plot_height + 36.0, unit,
+                12.0, MARGIN + plot_height / 2.0));
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// equipotential lines with marching squares, one path per level
+    fn svg_contours(&self, svg: &mut String, frame: &Frame, settings: &SvgPlot) {
+        if settings.contour_levels == 0 { return; }
+        let height = self.height();
+        let (min, max) = self.nodes.iter().fold((f64::INFINITY, f64::NEG_INFINITY),
+                                                |(min, max), &v| (min.min(v), max.max(v)));
+        if max <= min { return; }
+
+        for level_index in 0..settings.contour_levels {
+            let t = (level_index as f64 + 0.5) / settings.contour_levels as f64;
+            let level = min + (max - min) * t;
+            let color = settings.colormap.color(t);
+            let mut path = String::new();
+            for row in 0..height - 1 {
+                for column in 0..self.width - 1 {
+                    for &(a, b) in contour_cell(self, column, row, level).iter().flatten() {
+                        let (x, y) = self.scale.to_external(a.0, a.1);
+                        let (x_a, y_a) = frame.point(x, y);
+                        let (x, y) = self.scale.to_external(b.0, b.1);
+                        let (x_b, y_b) = frame.point(x, y);
+                        path.push_str(&format!("M{:.2} {:.2}L{:.2} {:.2}", x_a, y_a, x_b, y_b));
+                    }
+                }
+            }
+            if !path.is_empty() {
+                svg.push_str(&format!(
+                    "<path d=\"{}\" fill=\"none\" stroke=\"rgb({},{},{})\" stroke-width=\"1\"/>\n",
+                    path, color[0], color[1], color[2]));
+            }
+        }
+    }
+
+    /// arrows of the electric field, the longest arrow is as long as the spacing between arrows
+    /// (the mean spacing on graded axes)
+    fn svg_arrows(&self, svg: &mut String, frame: &Frame, settings: &SvgPlot) {
+        if settings.arrow_spacing == 0 { return; }
+        let height = self.height();
+        let field = self.electric_field();
+        let spacing = settings.arrow_spacing;
+        let offset = spacing / 2;
+
+        let mut max_magnitude: f64 = 0.0;
+        for row in (offset..height).step_by(spacing) {
+            for column in (offset..self.width).step_by(spacing) {
+                let (e_x, e_y) = field[row * self.width + column];
+                max_magnitude = max_magnitude.max((e_x * e_x + e_y * e_y).sqrt());
+            }
+        }
+        if max_magnitude == 0.0 { return; }
+        let x_per_column = (self.scale.x.to_external((self.width - 1) as f64)
+            - self.scale.x.to_external(0.0)).abs() / (self.width - 1).max(1) as f64;
+        let arrow_length = spacing as f64 * x_per_column * frame.scale;
+
+        svg.push_str("<g stroke=\"dimgray\" fill=\"dimgray\" stroke-width=\"0.8\">\n");
+        for row in (offset..height).step_by(spacing) {
+            for column in (offset..self.width).step_by(spacing) {
+                let (e_x, e_y) = field[row * self.width + column];
+                let magnitude = (e_x * e_x + e_y * e_y).sqrt();
+                if magnitude < max_magnitude * 1e-3 { continue; }
+                let (x, y) = self.scale.to_external(column as f64, row as f64);
+                let (x_0, y_0) = frame.point(x, y);
+                // svg y grows downwards
+                let length = arrow_length * magnitude / max_magnitude;
+                let (d_x, d_y) = (e_x / magnitude, -e_y / magnitude);
+                let (x_1, y_1) = (x_0 + d_x * length, y_0 + d_y * length);
+                let head = length.min(arrow_length) * 0.3;
+                svg.push_str(&format!(
+                    "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\"/>\
+                     <polygon points=\"{:.2},{:.2} {:.2},{:.2} {:.2},{:.2}\"/>\n",
+                    x_0, y_0, x_1, y_1,
+                    x_1, y_1,
+                    x_1 - d_x * head - d_y * head * 0.5, y_1 - d_y * head + d_x * head * 0.5,
+                    x_1 - d_x * head + d_y * head * 0.5, y_1 - d_y * head - d_x * head * 0.5));
+            }
+        }
+        svg.push_str("</g>\n");
+    }
+}
+
+type Segment = ((f64, f64), (f64, f64));
+
+/// the (up to two) segments of the equipotential line `level` inside the cell with its top left
+/// node at `column`, `row`; the points are in internal coordinates
+fn contour_cell(grid: &Grid, column: usize, row: usize, level: f64) -> [Option<Segment>; 2] {
+    let value = |c: usize, r: usize| grid.nodes[r * grid.width + c];
+    let corners = [
+        (column as f64, row as f64, value(column, row)),
+        (column as f64 + 1.0, row as f64, value(column + 1, row)),
+        (column as f64 + 1.0, row as f64 + 1.0, value(column + 1, row + 1)),
+        (column as f64, row as f64 + 1.0, value(column, row + 1)),
+    ];
+    // crossing on edge i (from corner i to corner i + 1)
+    let crossing = |i: usize| {
+        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+        let t = if b.2 != a.2 { (level - a.2) / (b.2 - a.2) } else { 0.5 };
+        (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+    };
+    let mut case = 0;
+    for (i, corner) in corners.iter().enumerate() {
+        if corner.2 > level { case |= 1 << i; }
+    }
+    let segment = |a: usize, b: usize| Some((crossing(a), crossing(b)));
+    match case {
+        0 | 15 => [None, None],
+        1 | 14 => [segment(3, 0), None],
+        2 | 13 => [segment(0, 1), None],
+        3 | 12 => [segment(3, 1), None],
+        4 | 11 => [segment(1, 2), None],
+        6 | 9 => [segment(0, 2), None],
+        7 | 8 => [segment(2, 3), None],
+        // saddles, the mean of the corners decides which corners are connected
+        5 | 10 => {
+            let center = corners.iter().map(|c| c.2).sum::<f64>() / 4.0;
+            if (center > level) == (case == 5) {
+                [segment(3, 2), segment(0, 1)]
+            } else {
+                [segment(3, 0), segment(1, 2)]
+            }
+        }
+        _ => unreachable!(),
+    }
+}
@@ -12,28 +12,32 @@ use std::slice;
 use std::io;
 use std::io::Write;
 
+use super::MAX_ITERATIONS;
+
+/// The stencil the workers relax the nodes with
+pub(crate) trait WeightedMean: Sync {
+    /// the weighted mean of the neighbours of node `i`, the `k`th dynamic node
+    fn mean(&self, nodes: &[f64], k: usize, i: usize) -> f64;
+}
+
 struct Worker {
     handle: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    pub fn new(receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
-               sender: mpsc::Sender<(usize, f64)>, nodes: &mut [f64], width: usize,
-               dynamic_nodes: &[usize], over_relaxation: f64) -> Worker {
+    pub fn new<M: WeightedMean>(receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+               sender: mpsc::Sender<(usize, f64)>, nodes: &mut [f64],
+               dynamic_nodes: &[usize], stencil: &M, over_relaxation: f64) -> Worker {
         Worker {
             handle: Some(unsafe { crossbeam_utils::scoped::spawn_unsafe(move || loop {
                 let message = receiver.lock().unwrap().recv().unwrap();
                 match message {
                     Message::Do((sector, start, end)) => {
                         let mut max_delta = 0.0;
-                        for &i in dynamic_nodes[start..end].iter() {
-                            let mut new_value = nodes[i-width];
-                            new_value += nodes[i-1];
-                            new_value += nodes[i+1];
-                            new_value += nodes[i+width];
-                            new_value /= 4.0;
-
-                            let mut delta = nodes[i] - new_value;
+                        for (k, &i) in dynamic_nodes.iter().enumerate().take(end).skip(start) {
+                            let new_value = stencil.mean(nodes, k, i);
+
+                            let delta = nodes[i] - new_value;
                             nodes[i] -= over_relaxation*delta;
                             if delta.abs() > max_delta {max_delta = delta.abs()};
                         }
@@ -78,7 +82,11 @@ impl Lock {
 }
 
 impl ThreadPool {
-    pub fn new(size: usize, grid: &mut super::Grid, over_relaxation: f64) -> ThreadPool {
+    /// `stride` is the largest distance between a node and one of its neighbours in `nodes`
+    /// (the width of a 2D grid, a whole layer of a 3D one), every sector spans at least two of
+    /// them so the sectors that are evaluated at the same time never share a neighbour
+    pub fn new<M: WeightedMean>(size: usize, nodes: &mut [f64], dynamic_nodes: &[usize],
+                                stride: usize, stencil: &M, over_relaxation: f64) -> ThreadPool {
         assert!(size > 1);
 
         let (sender_pool, receiver_threads) = mpsc::channel();
@@ -86,31 +94,34 @@ impl ThreadPool {
 
         let (sender_threads, receiver_pool) = mpsc::channel();
 
-        let max_sections = grid.dynamic_nodes_indices.len() / (2 * grid.width);
+        let max_sections = dynamic_nodes.len() / (2 * stride);
         let size = if size * 2 > max_sections { max_sections } else { size * 2 };
 
-        let ptr = grid.nodes.as_mut_ptr();
+        let ptr = nodes.as_mut_ptr();
 
         let mut workers = Vec::with_capacity(size / 2);
         for _id in 0..workers.capacity() {
             workers.push(Worker::new(Arc::clone(&receiver_threads),
                                      mpsc::Sender::clone(&sender_threads),
-                                     unsafe { slice::from_raw_parts_mut(ptr, grid.nodes.len())},
-                                     grid.width,
-                                     grid.dynamic_nodes_indices.as_slice(),
+                                     unsafe { slice::from_raw_parts_mut(ptr, nodes.len())},
+                                     dynamic_nodes,
+                                     stencil,
                                      over_relaxation));
         }
 
         ThreadPool { workers, sender: sender_pool, receiver: receiver_pool }
     }
 
-    pub fn evaluate(&self, accepted_delta: f64, dynamic_indices_len: usize) {
+    /// Relaxes the sectors until the max delta of every sector is at most `accepted_delta`,
+    /// returns whether they converged. Like `Grid::evaluate` it gives up once a delta is not
+    /// finite or every sector was relaxed `MAX_ITERATIONS` times
+    pub fn evaluate(&self, accepted_delta: f64, dynamic_indices_len: usize) -> bool {
         let sector_width = dynamic_indices_len / (self.workers.len() * 2);
         let mut dynamic_sectors = Vec::with_capacity(self.workers.len() * 2 + 1);
         for i in 0..self.workers.len() * 2 {
             dynamic_sectors.push(sector_width * i)
         }
-        dynamic_sectors.push(dynamic_indices_len - 1);
+        dynamic_sectors.push(dynamic_indices_len);
 
         let mut sector_locks = vec![Lock::Two; self.workers.len() * 2];
         let last_sector_index = self.workers.len() * 2 - 1;
@@ -124,10 +135,15 @@ impl ThreadPool {
 
         let max_delta = accepted_delta + 1.0;
         let mut sector_deltas = vec![max_delta; self.workers.len() * 2];
+        let mut relaxed_sectors = 0;
         while sector_deltas.iter().any(|&d| d > accepted_delta) {
             let (sector, delta) = self.receiver.recv().unwrap();
             print!("\rdelta {}", delta);
             io::stdout().flush().expect("Could not flush stdout!");
+            relaxed_sectors += 1;
+            if !delta.is_finite() || relaxed_sectors >= MAX_ITERATIONS * sector_deltas.len() {
+                return false;
+            }
             sector_deltas[sector] = delta;
             if sector == 0 {
                 match sector_locks[sector + 1] {
@@ -195,6 +211,7 @@ impl ThreadPool {
                 }
             }
         }
+        true
     }
 }
 
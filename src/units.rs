@@ -0,0 +1,105 @@
+//! # Units
+//!
+//! the physical units the coordinates and potentials of a grid are given in. The solver itself
+//! does not care about them, they are carried along for labels and files.
+
+/// Unit of the external coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthUnit {
+    Micrometer,
+    Millimeter,
+    Meter,
+}
+
+impl LengthUnit {
+    /// symbol used in labels and files
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            LengthUnit::Micrometer => "µm",
+            LengthUnit::Millimeter => "mm",
+            LengthUnit::Meter => "m",
+        }
+    }
+
+    /// length of one unit in meters
+    pub fn in_meters(&self) -> f64 {
+        match *self {
+            LengthUnit::Micrometer => 1e-6,
+            LengthUnit::Millimeter => 1e-3,
+            LengthUnit::Meter => 1.0,
+        }
+    }
+
+    /// the unit with the given symbol ("um" is accepted for "µm")
+    pub fn from_symbol(symbol: &str) -> Option<LengthUnit> {
+        match symbol {
+            "µm" | "um" => Some(LengthUnit::Micrometer),
+            "mm" => Some(LengthUnit::Millimeter),
+            "m" => Some(LengthUnit::Meter),
+            _ => None,
+        }
+    }
+}
+
+/// Unit of the potential
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PotentialUnit {
+    Volt,
+    Kilovolt,
+}
+
+impl PotentialUnit {
+    /// symbol used in labels and files
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            PotentialUnit::Volt => "V",
+            PotentialUnit::Kilovolt => "kV",
+        }
+    }
+
+    /// one unit in volts
+    pub fn in_volts(&self) -> f64 {
+        match *self {
+            PotentialUnit::Volt => 1.0,
+            PotentialUnit::Kilovolt => 1e3,
+        }
+    }
+
+    /// the unit with the given symbol
+    pub fn from_symbol(symbol: &str) -> Option<PotentialUnit> {
+        match symbol {
+            "V" => Some(PotentialUnit::Volt),
+            "kV" => Some(PotentialUnit::Kilovolt),
+            _ => None,
+        }
+    }
+}
+
+/// The unit system of a grid, the electric field is given in potential unit per length unit
+///
+/// # Fields
+///
+/// * `length` - unit of the coordinates
+/// * `potential` - unit of the potential
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Units {
+    pub length: LengthUnit,
+    pub potential: PotentialUnit,
+}
+
+impl Units {
+    /// millimeters and volts
+    pub const DEFAULT: Units = Units { length: LengthUnit::Millimeter,
+        potential: PotentialUnit::Volt };
+
+    /// symbol of the unit of the electric field, e.g. "V/mm"
+    pub fn field_symbol(&self) -> String {
+        format!("{}/{}", self.potential.symbol(), self.length.symbol())
+    }
+}
+
+impl Default for Units {
+    fn default() -> Units {
+        Units::DEFAULT
+    }
+}
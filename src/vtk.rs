@@ -0,0 +1,206 @@
+//! # VTK Export
+//!
+//! writes grids as VTK image data (legacy `.vtk` or xml `.vti`) for ParaView. Both contain the
+//! potential as point scalars, the electric field as point vectors and a mask of the fixed nodes.
+//! The points are ordered by increasing external coordinates, so inverted axes end up the right
+//! way round. Image data needs evenly spaced axes, grids with graded axes are written as a
+//! rectilinear grid (legacy format only). A `Grid3` is written as a volume the same way
+
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+use super::{Grid, Grid3};
+
+/// the header of a legacy file, the dataset follows it
+const LEGACY_HEADER: &str = "# vtk DataFile Version 3.0\nnumeric_laplace grid\nASCII\n";
+
+/// The points of a 2D or 3D grid in the order they are written and the data at them, a 2D grid
+/// is a volume one point deep
+struct Points<'a, F: Fn(usize) -> [f64; 3]> {
+    /// points along x, y and z
+    dimensions: [usize; 3],
+    /// external coordinates of the first point
+    origin: [f64; 3],
+    spacing: [f64; 3],
+    /// node indices with x growing fastest, then y and then z
+    indices: Vec<usize>,
+    nodes: &'a [f64],
+    fixed: Vec<bool>,
+    /// the electric field at a node
+    field: F,
+}
+
+impl<'a, F: Fn(usize) -> [f64; 3]> Points<'a, F> {
+    /// the structured points dataset of a legacy file
+    fn structured_points(&self) -> String {
+        let [width, height, depth] = self.dimensions;
+        format!("DATASET STRUCTURED_POINTS\nDIMENSIONS {} {} {}\nORIGIN {} {} {}\n\
+                 SPACING {} {} {}\n", width, height, depth, self.origin[0], self.origin[1],
+                self.origin[2], self.spacing[0], self.spacing[1], self.spacing[2])
+    }
+
+    /// the point data of a legacy file, one value per line
+    fn legacy_point_data(&self) -> String {
+        let mut data = String::with_capacity(self.indices.len() * 50);
+        data.push_str(&format!("POINT_DATA {}\n", self.indices.len()));
+        data.push_str("SCALARS potential double 1\nLOOKUP_TABLE default\n");
+        for &i in self.indices.iter() {
+            data.push_str(&format!("{}\n", self.nodes[i]));
+        }
+        data.push_str("VECTORS electric_field double\n");
+        for &i in self.indices.iter() {
+            let field = (self.field)(i);
+            data.push_str(&format!("{} {} {}\n", field[0], field[1], field[2]));
+        }
+        data.push_str("SCALARS fixed unsigned_char 1\nLOOKUP_TABLE default\n");
+        for &i in self.indices.iter() {
+            data.push_str(if self.fixed[i] { "1\n" } else { "0\n" });
+        }
+        data
+    }
+
+    /// a complete xml image data file
+    fn image_data(&self) -> String {
+        let [width, height, depth] = self.dimensions;
+        let mut file_string = String::with_capacity(self.indices.len() * 60);
+        let extent = format!("0 {} 0 {} 0 {}", width - 1, height - 1, depth - 1);
+        file_string.push_str(&format!(
+            "<?xml version=\"1.0\"?>\n\
+             <VTKFile type=\"ImageData\" version=\"0.1\" byte_order=\"LittleEndian\">\n\
+             <ImageData WholeExtent=\"{0}\" Origin=\"{1} {2} {3}\" Spacing=\"{4} {5} {6}\">\n\
+             <Piece Extent=\"{0}\">\n\
+             <PointData Scalars=\"potential\" Vectors=\"electric_field\">\n",
+            extent, self.origin[0], self.origin[1], self.origin[2], self.spacing[0],
+            self.spacing[1], self.spacing[2]));
+
+        file_string.push_str(
+            "<DataArray type=\"Float64\" Name=\"potential\" format=\"ascii\">\n");
+        for &i in self.indices.iter() {
+            file_string.push_str(&format!("{} ", self.nodes[i]));
+        }
+        file_string.push_str("\n</DataArray>\n<DataArray type=\"Float64\" Name=\"electric_field\" \
+                              NumberOfComponents=\"3\" format=\"ascii\">\n");
+        for &i in self.indices.iter() {
+            let field = (self.field)(i);
+            file_string.push_str(&format!("{} {} {} ", field[0], field[1], field[2]));
+        }
+        file_string.push_str(
+            "\n</DataArray>\n<DataArray type=\"UInt8\" Name=\"fixed\" format=\"ascii\">\n");
+        for &i in self.indices.iter() {
+            file_string.push_str(if self.fixed[i] { "1 " } else { "0 " });
+        }
+        file_string.push_str("\n</DataArray>\n</PointData>\n<CellData>\n</CellData>\n</Piece>\n\
+                              </ImageData>\n</VTKFile>\n");
+        file_string
+    }
+}
+
+impl Grid {
+    /// the points of the grid with increasing external coordinates
+    fn points(&self) -> Points<'_, impl Fn(usize) -> [f64; 3]> {
+        let height = self.height();
+        let column = |i: usize| if self.scale.x.invert { self.width - 1 - i } else { i };
+        let row = |j: usize| if self.scale.y.invert { height - 1 - j } else { j };
+
+        let mut indices = Vec::with_capacity(self.nodes.len());
+        for j in 0..height {
+            for i in 0..self.width {
+                indices.push(row(j) * self.width + column(i));
+            }
+        }
+        let origin = self.scale.to_external(column(0) as f64, row(0) as f64);
+        let field = self.electric_field();
+        Points {
+            dimensions: [self.width, height, 1],
+            origin: [origin.0, origin.1, 0.0],
+            spacing: [self.scale.x.spacing, self.scale.y.spacing, 1.0],
+            indices,
+            nodes: &self.nodes,
+            fixed: self.fixed_mask(),
+            field: move |i: usize| [field[i].0, field[i].1, 0.0],
+        }
+    }
+
+    /// writes the grid as a legacy (ascii) vtk file with structured points, or as a rectilinear
+    /// grid if an axis is graded
+    pub fn to_vtk(&self, path: &str) -> std::io::Result<()> {
+        let points = self.points();
+        let mut file_string = String::from(LEGACY_HEADER);
+        if self.scale.x.is_graded() || self.scale.y.is_graded() {
+            // the first row of points holds every column, the first point of every row each row
+            let x: Vec<f64> = points.indices[..self.width].iter()
+                .map(|&i| self.scale.x.to_external((i % self.width) as f64)).collect();
+            let y: Vec<f64> = points.indices.iter().step_by(self.width)
+                .map(|&i| self.scale.y.to_external((i / self.width) as f64)).collect();
+            file_string.push_str(&format!("DATASET RECTILINEAR_GRID\nDIMENSIONS {} {} 1\n",
+                                          self.width, self.height()));
+            for &(name, coordinates) in [("X", &x), ("Y", &y)].iter() {
+                file_string.push_str(&format!("{}_COORDINATES {} double\n", name,
+                                              coordinates.len()));
+                for coordinate in coordinates.iter() {
+                    file_string.push_str(&format!("{} ", coordinate));
+                }
+                file_string.push('\n');
+            }
+            file_string.push_str("Z_COORDINATES 1 double\n0\n");
+        } else {
+            file_string.push_str(&points.structured_points());
+        }
+        file_string.push_str(&points.legacy_point_data());
+        File::create(path)?.write_all(file_string.as_bytes())
+    }
+
+    /// writes the grid as a xml vtk image data file (`.vti`) with ascii data arrays, fails for
+    /// graded axes (use `to_vtk` for them)
+    pub fn to_vti(&self, path: &str) -> std::io::Result<()> {
+        if self.scale.x.is_graded() || self.scale.y.is_graded() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "image data needs evenly spaced axes, use to_vtk"));
+        }
+        File::create(path)?.write_all(self.points().image_data().as_bytes())
+    }
+}
+
+impl Grid3 {
+    /// the points of the grid with increasing external coordinates
+    fn points(&self) -> Points<'_, impl Fn(usize) -> [f64; 3]> {
+        let (width, height, depth) = (self.width, self.height, self.depth());
+        let column = |i: usize| if self.scale.x.invert { width - 1 - i } else { i };
+        let row = |j: usize| if self.scale.y.invert { height - 1 - j } else { j };
+        let layer = |k: usize| if self.scale.z.invert { depth - 1 - k } else { k };
+
+        let mut indices = Vec::with_capacity(self.nodes.len());
+        for k in 0..depth {
+            for j in 0..height {
+                for i in 0..width {
+                    indices.push((layer(k) * height + row(j)) * width + column(i));
+                }
+            }
+        }
+        let origin = self.scale.to_external(column(0) as f64, row(0) as f64, layer(0) as f64);
+        let field = self.electric_field();
+        Points {
+            dimensions: [width, height, depth],
+            origin: [origin.0, origin.1, origin.2],
+            spacing: [self.scale.x.spacing, self.scale.y.spacing, self.scale.z.spacing],
+            indices,
+            nodes: &self.nodes,
+            fixed: self.fixed_mask(),
+            field: move |i: usize| [field[i].0, field[i].1, field[i].2],
+        }
+    }
+
+    /// writes the grid as a legacy (ascii) vtk file with structured points
+    pub fn to_vtk(&self, path: &str) -> std::io::Result<()> {
+        let points = self.points();
+        let file_string = String::from(LEGACY_HEADER) + &points.structured_points() +
+            &points.legacy_point_data();
+        File::create(path)?.write_all(file_string.as_bytes())
+    }
+
+    /// writes the grid as a xml vtk image data file (`.vti`) with ascii data arrays
+    pub fn to_vti(&self, path: &str) -> std::io::Result<()> {
+        File::create(path)?.write_all(self.points().image_data().as_bytes())
+    }
+}
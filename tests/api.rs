@@ -0,0 +1,262 @@
+//! Focused checks of the public API besides the solver: profiles, builders, errors and the
+//! analysis helpers.
+
+extern crate numeric_laplace;
+
+mod common;
+
+use numeric_laplace::*;
+use common::{grid_of, SCALE};
+
+#[test]
+fn polyline_profile_follows_a_linear_potential() {
+    let grid = grid_of(11, 11, |x, y| 2.0 * x - y);
+    let points = [(1.0, 1.0), (9.0, 1.0), (9.0, 7.0)];
+    let profile = grid.polyline_profile(&points, 8, Interpolation::Bilinear).unwrap();
+    assert_eq!(profile.len(), 8);
+    assert_eq!((profile[7].x, profile[7].y), (9.0, 7.0));
+    for sample in profile.iter() {
+        assert!((sample.potential - (2.0 * sample.x - sample.y)).abs() < 1e-12);
+        assert!((sample.field.0 + 2.0).abs() < 1e-9 && (sample.field.1 - 1.0).abs() < 1e-9);
+        // 8 units along x and then 6 along y
+        let along = if sample.y == 1.0 { sample.x - 1.0 } else { 8.0 + sample.y - 1.0 };
+        assert!((sample.distance - along).abs() < 1e-12);
+    }
+
+    assert_eq!(grid.polyline_profile(&points[..1], 8, Interpolation::Bilinear),
+               Err(SampleError::TooFewPoints { points: 1, samples: 8 }));
+    assert!(grid.line_profile((1.0, 1.0), (2.0, 2.0), 1, Interpolation::Bicubic).is_err());
+}
+
+/// a plate capacitor in a grounded box
+fn capacitor() -> GridBuilder<'static> {
+    GridBuilder::new().size(24, 16)
+        .electrode(FixedBox { x: 6, y: 4, width: 2, height: 8, potential: 1.0 })
+        .electrode(FixedBox { x: 16, y: 4, width: 2, height: 8, potential: -1.0 })
+}
+
+/// the biggest difference of the dynamic nodes of `grid` from `reference`
+fn max_dynamic_difference(grid: &Grid, reference: &Grid) -> f64 {
+    grid.nodes.iter().zip(reference.nodes.iter()).zip(grid.fixed_mask())
+        .filter(|&(_, fixed)| !fixed)
+        .fold(0.0, |max, ((a, b), _)| f64::max(max, (a - b).abs()))
+}
+
+#[test]
+fn initial_guesses_fill_only_the_dynamic_nodes() {
+    for &(ref guess, value) in [(InitialGuess::Zero, 0.0), (InitialGuess::Constant(2.5), 2.5)]
+        .iter() {
+        let grid = capacitor().initial_guess(guess.clone()).build().unwrap();
+        let fixed = grid.fixed_mask();
+        for (i, &node) in grid.nodes.iter().enumerate() {
+            let (x, y) = ((i % grid.width) as isize, (i / grid.width) as isize);
+            let expected = if !fixed[i] { value }
+                else if (6..8).contains(&x) && (4..12).contains(&y) { 1.0 }
+                else if (16..18).contains(&x) && (4..12).contains(&y) { -1.0 }
+                else { 0.0 };
+            assert_eq!(node, expected);
+        }
+    }
+
+    // a linear potential is interpolated exactly from a bigger grid with a different spacing
+    let existing = Grid::from_parts((0..41 * 41).map(|i| (i % 41) as f64 - 0.5 * (i / 41) as f64)
+                                        .collect(), 41, &[false; 41 * 41],
+                                    Scale { x: Axis { spacing: 0.75, ..SCALE.x },
+                                            y: Axis { spacing: 0.75, ..SCALE.y }, ..SCALE });
+    let grid = capacitor().initial_guess(InitialGuess::Existing(&existing)).build().unwrap();
+    for (i, (&node, fixed)) in grid.nodes.iter().zip(grid.fixed_mask()).enumerate() {
+        let (x, y) = ((i % grid.width) as f64, (i / grid.width) as f64);
+        if !fixed {
+            assert!((node - (x - 0.5 * y) / 0.75).abs() < 1e-12);
+        }
+    }
+}
+
+#[test]
+fn coarse_guess_starts_close_to_the_solution() {
+    let mut solved = capacitor().initial_guess(InitialGuess::Zero).build().unwrap();
+    while solved.sweep(1.8) > 1e-12 {}
+    let zero = capacitor().initial_guess(InitialGuess::Zero).build().unwrap();
+    let coarse = capacitor()
+        .initial_guess(InitialGuess::Coarse { factor: 2, accepted_delta: 1e-10,
+                                              over_relaxation: 1.6 })
+        .build().unwrap();
+    assert!(max_dynamic_difference(&coarse, &solved) <
+            0.5 * max_dynamic_difference(&zero, &solved));
+
+    for &(factor, over_relaxation) in [(0, 1.5), (2, 2.0), (2, 0.0)].iter() {
+        let guess = InitialGuess::Coarse { factor, accepted_delta: 1e-10, over_relaxation };
+        assert!(matches!(capacitor().initial_guess(guess).build(),
+                         Err(GridError::InvalidInitialGuess(_))));
+    }
+    // no max delta is negative, so the coarse grid runs into the iteration limit
+    let guess = InitialGuess::Coarse { factor: 4, accepted_delta: -1.0, over_relaxation: 1.5 };
+    assert!(matches!(capacitor().initial_guess(guess).build(),
+                     Err(GridError::InvalidInitialGuess(_))));
+}
+
+#[test]
+fn richardson_extrapolation_recovers_a_second_order_sequence() {
+    // phi(h) = 2 + 0.3 h^2, with a constant and with a varying refinement ratio
+    let phi = |resolution: usize| 2.0 + 0.3 / (resolution * resolution) as f64;
+    for resolutions in [[8, 4, 2], [12, 6, 4]].iter() {
+        let values = resolutions.iter().map(|&r| phi(r)).collect();
+        let probe = ProbeConvergence::new(1.0, 2.0, resolutions, values).unwrap();
+        assert!((probe.observed_order - 2.0).abs() < 1e-9);
+        assert!((probe.extrapolated - 2.0).abs() < 1e-12);
+        // the error of the finest value relative to it, times the safety factor of 1.25
+        let ratio = (resolutions[0] / resolutions[1]) as f64;
+        let gci = 1.25 * (phi(resolutions[1]) - phi(resolutions[0])) / phi(resolutions[0]) /
+            (ratio * ratio - 1.0);
+        assert!((probe.gci - gci).abs() < 1e-12);
+    }
+
+    let constant = ProbeConvergence::new(0.0, 0.0, &[4, 2, 1], vec![1.0; 3]).unwrap();
+    assert!(constant.observed_order.is_nan() && constant.extrapolated == 1.0);
+    assert!(matches!(ProbeConvergence::new(0.0, 0.0, &[4, 2], vec![1.0; 2]),
+                     Err(ConvergenceError::InvalidResolutions(_))));
+    assert!(matches!(ProbeConvergence::new(0.0, 0.0, &[2, 4, 8], vec![1.0; 3]),
+                     Err(ConvergenceError::InvalidResolutions(_))));
+    assert!(matches!(convergence_study(&GridBuilder::new().size(4, 4), &[2, 1, 2], &[], 1e-6,
+                                       1.5),
+                     Err(ConvergenceError::InvalidResolutions(_))));
+    // the finest resolution is solved first, a diverging solve stops the study
+    assert_eq!(convergence_study(&capacitor(), &[1, 2, 4], &[(12.0, 8.0)], 1e-6, 2.5).err(),
+               Some(ConvergenceError::NotConverged { nodes_per_unit: 4 }));
+}
+
+#[test]
+fn grid_construction_names_the_offending_element() {
+    let inside = FixedBox { x: 2, y: 2, width: 3, height: 3, potential: 1.0 };
+    let build = |boxes: &[FixedBox]| Grid::new(10, 8, boxes, &SCALE, Some([7; 32]));
+
+    let too_wide = FixedBox { x: 9, y: 0, width: 2, height: 1, potential: 1.0 };
+    assert_eq!(build(&[inside.clone(), too_wide.clone()]).err(),
+               Some(GridError::OutOfBounds { index: 1, fixed_box: too_wide }));
+    let negative = FixedBox { x: -1, y: 3, width: 2, height: 1, potential: 1.0 };
+    assert!(matches!(build(&[negative]), Err(GridError::OutOfBounds { index: 0, .. })));
+
+    let overlapping = FixedBox { x: 4, y: 3, width: 2, height: 2, potential: -1.0 };
+    let error = build(&[inside.clone(), overlapping.clone()]).err().unwrap();
+    assert_eq!(error, GridError::OverlapConflict { first: 0, second: 1, x: 4.0, y: 3.0 });
+    assert!(error.to_string().contains("fixed boxes 0 and 1"));
+    // overlapping boxes with the same potential are fine
+    let same = FixedBox { potential: 1.0, ..overlapping };
+    assert!(build(&[inside, same]).is_ok());
+
+    assert_eq!(Grid::new(0, 8, &[], &SCALE, None).err(),
+               Some(GridError::ZeroSize { width: 0, height: 8 }));
+    let flat = Scale { y: Axis { spacing: 0.0, ..SCALE.y }, ..SCALE };
+    assert!(matches!(Grid::new(10, 8, &[], &flat, None), Err(GridError::InvalidScale(_))));
+}
+
+fn tilted(x: f64, y: f64) -> f64 { x + 10.0 * y }
+
+#[test]
+fn grid_builder_chains_the_whole_task_into_an_owned_grid() {
+    let builder = GridBuilder::new().size(5, 4).resolution(2).origin(1.0, 3.0)
+        .invert(false, true).units(LengthUnit::Micrometer, PotentialUnit::Kilovolt)
+        .electrode(FixedBox { x: 2, y: 2, width: 1, height: 1, potential: 7.0 })
+        .boundary(BoundaryCondition::Function(tilted)).seed([3; 32]);
+    let grid = builder.build().unwrap();
+
+    assert_eq!((grid.width, grid.height()), (10, 8));
+    assert_eq!(grid.scale.units.length, LengthUnit::Micrometer);
+    // row 0 is at y = 3 and the rows go down from there, half a unit apart
+    assert_eq!(grid.scale.to_external(4.0, 2.0), (3.0, 2.0));
+    let fixed = grid.fixed_mask();
+    for (i, &node) in grid.nodes.iter().enumerate() {
+        let (column, row) = (i % grid.width, i / grid.width);
+        let (x, y) = grid.scale.to_external(column as f64, row as f64);
+        if column == 0 || row == 0 || column == 9 || row == 7 {
+            assert!(fixed[i] && node == tilted(x, y));
+        } else if (2.0..3.0).contains(&x) && y <= 2.0 && y > 1.0 {
+            assert!(fixed[i] && node == 7.0, "({}, {})", x, y);
+        } else {
+            assert!(!fixed[i]);
+        }
+    }
+
+    // the builder is not consumed and the same seed gives the same noise
+    assert_eq!(builder.build().unwrap().nodes, grid.nodes);
+    let other_seed = builder.clone().seed([4; 32]).build().unwrap();
+    assert_ne!(other_seed.nodes, grid.nodes);
+    // the grid owns its scale, so it can be moved to another thread
+    let solved = std::thread::spawn(move || {
+        let mut grid = grid;
+        while grid.sweep(1.5) > 1e-9 {}
+        grid
+    }).join().unwrap();
+    assert_eq!(solved.width, 10);
+}
+
+/// 1 on the right border of a grid from 0 to 9, 0 everywhere else
+fn right_edge(x: f64, _y: f64) -> f64 { if x > 8.5 { 1.0 } else { 0.0 } }
+
+#[test]
+fn coarse_guess_keeps_the_last_of_evenly_spaced_coordinates() {
+    // 9 cells can not be halved, the coarse grid still has to end at the last coordinate so the
+    // potential of the right border reaches the guess
+    let coordinates: Vec<f64> = (0..10).map(|i| i as f64).collect();
+    let guess = InitialGuess::Coarse { factor: 2, accepted_delta: 1e-10, over_relaxation: 1.5 };
+    let grid = GridBuilder::new().node_coordinates(&coordinates, &coordinates)
+        .boundary(BoundaryCondition::Function(right_edge)).initial_guess(guess).build().unwrap();
+    assert_eq!((grid.width, grid.height()), (10, 10));
+    assert!(!grid.scale.x.is_graded());
+    assert!(grid.nodes[5 * 10 + 8] > 0.2, "{}", grid.nodes[5 * 10 + 8]);
+}
+
+#[test]
+fn gradings_with_too_many_cells_are_rejected() {
+    let grading = Grading::new(&[0.0], 1e-9, 1.0, 1.0);
+    assert!(matches!(GridBuilder::new().size(100, 10).grade(Some(grading), None).build(),
+                     Err(GridError::InvalidScale(_))));
+}
+
+#[test]
+fn evaluate_gives_up_on_a_diverging_over_relaxation() {
+    let mut watch = Vec::new();
+    assert!(capacitor().build().unwrap().evaluate(1e-9, 1.5, (12, 8), &mut watch));
+    assert_eq!(watch.last(), Some(&f64::NEG_INFINITY));
+
+    // with an over relaxation above 2 every sweep overshoots further
+    let mut watch = Vec::new();
+    assert!(!capacitor().build().unwrap().evaluate(1e-9, 2.5, (12, 8), &mut watch));
+    assert_eq!(watch.last(), Some(&f64::NEG_INFINITY));
+    assert!(watch.len() < 100_000);
+    // a NaN delta of a sector must not look converged either
+    let mut grid = GridBuilder::new().size(64, 64).build().unwrap();
+    assert!(grid.evaluate_multi_thread(1e-9, 1.5, 2));
+    let mut grid = GridBuilder::new().size(64, 64).build().unwrap();
+    assert!(!grid.evaluate_multi_thread(1e-9, 2.5, 2));
+}
+
+#[test]
+fn currents_need_a_terminal_inside_of_a_conducting_sheet() {
+    let from = FixedBox { x: 0, y: 0, width: 1, height: 10, potential: 1.0 };
+    let to = FixedBox { x: 19, y: 0, width: 1, height: 10, potential: 0.0 };
+    let sheet = |background: f64| {
+        let conduction = Conduction { background, regions: Vec::new(), insulating_border: true };
+        GridBuilder::new().size(20, 10).electrode(from.clone()).electrode(to.clone())
+            .conduction(conduction).build().unwrap()
+    };
+
+    let outside = FixedBox { x: 18, y: 0, width: 4, height: 10, potential: 0.0 };
+    assert_eq!(sheet(1.0).terminal_current(&outside),
+               Err(GridError::TerminalOutOfBounds(outside.clone())));
+    assert_eq!(sheet(1.0).resistance(&from, &outside),
+               Err(GridError::TerminalOutOfBounds(outside)));
+    // an insulator carries no current, so it has no finite resistance
+    assert_eq!(sheet(0.0).terminal_current(&from), Ok(0.0));
+    assert_eq!(sheet(0.0).resistance(&from, &to), Err(GridError::NoCurrent));
+
+    // the potential of a terminal is the one of its nodes, so they have to be fixed
+    let floating = FixedBox { x: 5, y: 2, width: 2, height: 2, potential: 1.0 };
+    assert_eq!(sheet(1.0).terminal_current(&floating),
+               Err(GridError::FloatingTerminal(floating.clone())));
+    let overhanging = FixedBox { x: 0, y: 0, width: 2, height: 10, potential: 1.0 };
+    assert_eq!(sheet(1.0).resistance(&overhanging, &to),
+               Err(GridError::FloatingTerminal(overhanging)));
+    let empty = FixedBox { x: 0, y: 0, width: 0, height: 10, potential: 1.0 };
+    assert_eq!(sheet(1.0).terminal_current(&empty), Err(GridError::FloatingTerminal(empty)));
+}
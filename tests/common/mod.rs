@@ -0,0 +1,18 @@
+//! Helpers shared by the integration tests, not every test file uses all of them.
+#![allow(dead_code)]
+
+use numeric_laplace::*;
+
+/// one node per unit, no offsets, row 0 at the top
+pub const SCALE: Scale = Scale {
+    x: Axis { origin: 0.0, spacing: 1.0, invert: false, graded: None },
+    y: Axis { origin: 0.0, spacing: 1.0, invert: false, graded: None },
+    units: Units::DEFAULT,
+};
+
+/// a `width` x `height` grid holding `potential(x, y)` at every node, only the border is fixed
+pub fn grid_of<F: Fn(f64, f64) -> f64>(width: usize, height: usize, potential: F) -> Grid {
+    let nodes = (0..width * height)
+        .map(|i| potential((i % width) as f64, (i / width) as f64)).collect();
+    Grid::from_parts(nodes, width, &vec![false; width * height], SCALE)
+}
@@ -0,0 +1,326 @@
+//! Round trips and format checks of everything the grids are written to and read from.
+
+extern crate numeric_laplace;
+
+mod common;
+
+use numeric_laplace::*;
+use common::{grid_of, SCALE};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicBool;
+
+/// a path in the temporary directory that no other test (or test run) uses
+fn temporary(name: &str) -> String {
+    std::env::temp_dir().join(format!("numeric_laplace_{}_{}", std::process::id(), name))
+        .to_string_lossy().into_owned()
+}
+
+#[test]
+fn heat_map_writes_png_and_ppm_of_the_image_size() {
+    let grid = grid_of(10, 6, |x, _| x);
+    let settings = HeatMap { colormap: Colormap::Viridis, pixels_per_node: 3, range: None,
+                             colorbar: false, axis_labels: false, electrodes: &[] };
+    let image = grid.heat_map(&settings);
+    // a margin of 8 pixels around 10 x 6 nodes of 3 pixels
+    assert_eq!((image.width, image.height), (46, 34));
+    let first = (8 * image.width + 8) * 3;
+    assert_eq!(&image.pixels[first..first + 3], &Colormap::Viridis.color(0.0)[..]);
+
+    let (png, ppm) = (temporary("map.png"), temporary("map.ppm"));
+    image.save_png(&png).unwrap();
+    image.save_ppm(&ppm).unwrap();
+    let (png_bytes, ppm_bytes) = (fs::read(&png).unwrap(), fs::read(&ppm).unwrap());
+    fs::remove_file(&png).unwrap();
+    fs::remove_file(&ppm).unwrap();
+
+    assert_eq!(&png_bytes[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+    assert_eq!(&png_bytes[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+    assert_eq!(&png_bytes[16..24], &[0, 0, 0, 46, 0, 0, 0, 34]);
+    assert_eq!(&png_bytes[png_bytes.len() - 8..png_bytes.len() - 4], b"IEND");
+    let header = b"P6\n46 34\n255\n";
+    assert_eq!(&ppm_bytes[..header.len()], &header[..]);
+    assert_eq!(ppm_bytes.len(), header.len() + 46 * 34 * 3);
+}
+
+#[test]
+fn svg_contours_split_a_saddle_cell_by_its_center() {
+    // one cell with the corners 3 and 3 on a diagonal and -1 and -2 on the other one
+    let grid = grid_of(2, 2, |x, y| [[3.0, -1.0], [-2.0, 3.0]][y as usize][x as usize]);
+    let settings = SvgPlot { units_to_pixels: 100.0, contour_levels: 1, colormap: Colormap::Viridis,
+                             arrow_spacing: 0, electrodes: &[], axis_labels: false };
+    let path = temporary("plot.svg");
+    grid.to_svg(&path, &settings).unwrap();
+    let svg = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"120\" \
+                             height=\"120\""));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    // the only level is 0.5 and the mean of the corners is above it, so the line cuts off the
+    // two low corners instead of the two high ones; external y grows upwards in the svg
+    assert_eq!(svg.matches("<path").count(), 1);
+    assert!(svg.contains("d=\"M10.00 60.00L60.00 10.00M72.50 110.00L110.00 72.50\""));
+}
+
+#[test]
+fn vti_and_vtk_hold_every_point_by_increasing_coordinates() {
+    // y is inverted, so the last row of nodes comes first
+    let y = Axis { origin: 2.0, spacing: 1.0, invert: true, graded: None };
+    let scale = Scale { y, ..SCALE };
+    let nodes = (0..12).map(|i| (i % 4 + 10 * (i / 4)) as f64).collect();
+    let grid = Grid::from_parts(nodes, 4, &[false; 12], scale);
+    let (vti, vtk) = (temporary("grid.vti"), temporary("grid.vtk"));
+    grid.to_vti(&vti).unwrap();
+    grid.to_vtk(&vtk).unwrap();
+    let vti_text = fs::read_to_string(&vti).unwrap();
+    let vtk_text = fs::read_to_string(&vtk).unwrap();
+    fs::remove_file(&vti).unwrap();
+    fs::remove_file(&vtk).unwrap();
+
+    assert!(vti_text.contains("<ImageData WholeExtent=\"0 3 0 2 0 0\" Origin=\"0 0 0\" \
+                               Spacing=\"1 1 1\">"));
+    let potential = vti_text.split("Name=\"potential\" format=\"ascii\">\n").nth(1).unwrap()
+        .split('\n').next().unwrap();
+    let values: Vec<f64> = potential.split_whitespace().map(|v| v.parse().unwrap()).collect();
+    assert_eq!(values, [20.0, 21.0, 22.0, 23.0, 10.0, 11.0, 12.0, 13.0, 0.0, 1.0, 2.0, 3.0]);
+
+    assert!(vtk_text.starts_with("# vtk DataFile Version 3.0\n"));
+    assert!(vtk_text.contains("DATASET STRUCTURED_POINTS\nDIMENSIONS 4 3 1\nORIGIN 0 0 0\n"));
+    assert!(vtk_text.contains("POINT_DATA 12\nSCALARS potential double 1\nLOOKUP_TABLE default\n\
+                               20\n21\n"));
+    assert_eq!(vtk_text.split("SCALARS fixed unsigned_char 1\nLOOKUP_TABLE default\n").nth(1)
+                   .unwrap().lines().count(), 12);
+}
+
+/// a grid with offset and inverted axes, a fixed node inside and a potential that differs at
+/// every node
+fn uneven_grid() -> Grid {
+    let scale = Scale { x: Axis { origin: 1.5, spacing: 0.5, invert: false, graded: None },
+                        y: Axis { origin: 3.0, spacing: 1.0, invert: true, graded: None },
+                        units: Units::DEFAULT };
+    let mut fixed = vec![false; 20];
+    fixed[7] = true;
+    Grid::from_parts((0..20).map(|i| i as f64 * 0.25 - 1.0).collect(), 5, &fixed, scale)
+}
+
+/// nodes, fixed nodes and the external coordinates of every node are the same
+fn assert_same_grid(read: &Grid, written: &Grid) {
+    assert_eq!(read.nodes, written.nodes);
+    assert_eq!(read.width, written.width);
+    assert_eq!(read.fixed_mask(), written.fixed_mask());
+    for i in 0..written.nodes.len() {
+        let (column, row) = ((i % written.width) as f64, (i / written.width) as f64);
+        let (x, y) = written.scale.to_external(column, row);
+        let (read_x, read_y) = read.scale.to_external(column, row);
+        assert!((read_x - x).abs() < 1e-12 && (read_y - y).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn npy_and_npz_round_trip_nodes_mask_and_axes() {
+    let grid = uneven_grid();
+    let prefix = temporary("arrays");
+    grid.to_npy(&prefix).unwrap();
+    let from_npy = read_npy(&prefix).unwrap();
+    for name in ["potential", "fixed", "x", "y"].iter() {
+        fs::remove_file(format!("{}_{}.npy", prefix, name)).unwrap();
+    }
+    assert_same_grid(&from_npy, &grid);
+
+    let path = temporary("arrays.npz");
+    grid.to_npz(&path).unwrap();
+    let from_npz = read_npz(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_same_grid(&from_npz, &grid);
+    assert_eq!(from_npz.scale.units, Units::default());
+}
+
+#[test]
+fn truncated_or_corrupt_npz_archives_fail_without_panicking() {
+    let path = temporary("corrupt.npz");
+    uneven_grid().to_npz(&path).unwrap();
+    let archive = fs::read(&path).unwrap();
+
+    for length in 0..archive.len() {
+        fs::write(&path, &archive[..length]).unwrap();
+        assert!(read_npz(&path).is_err(), "truncated to {} bytes", length);
+    }
+    // a flipped byte in the data may still give a grid, but never a panic
+    for i in 0..archive.len() {
+        let mut corrupt = archive.clone();
+        corrupt[i] ^= 0xFF;
+        fs::write(&path, &corrupt).unwrap();
+        let _ = read_npz(&path);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn csv_round_trips_even_inverted_and_graded_axes() {
+    let graded = Axis { origin: -1.0, spacing: 0.5, invert: false,
+                        graded: Some(vec![0.0, 0.5, 1.5, 3.5]) };
+    let scales = [
+        SCALE,
+        Scale { x: Axis { origin: 4.0, spacing: 0.5, invert: true, graded: None },
+                y: Axis { origin: 2.0, spacing: 0.25, invert: true, graded: None }, ..SCALE },
+        Scale { x: graded.clone(), y: Axis { invert: true, ..graded }, ..SCALE },
+    ];
+    let path = temporary("round_trip.csv");
+    for scale in scales.iter() {
+        let nodes = (0..16).map(|i| (i as f64).sqrt() - 1.0 / 3.0).collect();
+        let grid = Grid::from_parts(nodes, 4, &[false; 16], scale.clone());
+        grid.to_csv(&path).unwrap();
+        let read = read_csv(&path).unwrap();
+        assert_eq!(read.nodes, grid.nodes);
+        assert_eq!(read.scale, grid.scale);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn csv_names_the_units_only_when_asked_to() {
+    let mut grid = grid_of(3, 3, |x, y| x * y);
+    grid.scale.units = Units { length: LengthUnit::Meter, potential: PotentialUnit::Kilovolt };
+    let path = temporary("units.csv");
+
+    grid.to_csv(&path).unwrap();
+    assert!(fs::read_to_string(&path).unwrap().starts_with("100,0,1,2\n"));
+    assert_eq!(read_csv(&path).unwrap().scale.units, Units::default());
+
+    grid.to_csv_with_units(&path).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("# length unit m, potential unit kV\n100,0,1,2\n"));
+    assert_eq!(read_csv(&path).unwrap().scale, grid.scale);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn malformed_csv_files_are_rejected() {
+    let path = temporary("malformed.csv");
+    let read = |contents: &str| {
+        fs::write(&path, contents).unwrap();
+        read_csv(&path)
+    };
+    match read("100,0,1,2\n0,1,2,3\n1,4,5\n2,7,8,9\n") {
+        Err(CsvError::RowLength { line: 3, expected: 4, found: 3 }) => {}
+        other => panic!("ragged row gave {:?}", other.map(|grid| grid.nodes)),
+    }
+    match read("100,0,1,2\n0,1,two,3\n1,4,5,6\n") {
+        Err(CsvError::InvalidNumber { line: 2, column: 3, ref text }) if text == "two" => {}
+        other => panic!("text cell gave {:?}", other.map(|grid| grid.nodes)),
+    }
+    for cell in ["NaN", "inf", "-inf"].iter() {
+        let contents = format!("100,0,1,2\n0,1,2,3\n1,4,{},6\n", cell);
+        assert!(matches!(read(&contents), Err(CsvError::InvalidNumber { line: 3, column: 3, .. })));
+    }
+    assert!(matches!(read("100,0,2,1\n0,1,2,3\n1,4,5,6\n"), Err(CsvError::InconsistentAxes(_))));
+    assert!(matches!(read("100,0,1,2\n0,1,2,3\n"), Err(CsvError::Empty)));
+    fs::remove_file(&path).unwrap();
+}
+
+/// a plate capacitor with random noise on the dynamic nodes, the same on every call
+fn noisy_capacitor() -> Grid {
+    GridBuilder::new().size(16, 12)
+        .electrode(FixedBox { x: 3, y: 3, width: 1, height: 6, potential: 1.0 })
+        .electrode(FixedBox { x: 12, y: 3, width: 1, height: 6, potential: -1.0 })
+        .seed([7; 32]).build().unwrap()
+}
+
+#[test]
+fn resumed_checkpoint_follows_the_uninterrupted_trajectory() {
+    const ITERATIONS: usize = 40;
+    let mut straight = noisy_capacitor();
+    let straight_residuals: Vec<f64> = (0..ITERATIONS).map(|_| straight.sweep(1.7)).collect();
+
+    let mut first_half = noisy_capacitor();
+    let mut state = SolverState::new(1.7);
+    for _ in 0..ITERATIONS / 2 {
+        state.residuals.push(first_half.sweep(state.over_relaxation));
+        state.iteration += 1;
+    }
+    let path = temporary("resume.chk");
+    first_half.save_checkpoint(&path, &state).unwrap();
+    let Checkpoint { grid: mut resumed, mut state } = Checkpoint::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(state.iteration, ITERATIONS / 2);
+    for _ in ITERATIONS / 2..ITERATIONS {
+        state.residuals.push(resumed.sweep(state.over_relaxation));
+        state.iteration += 1;
+    }
+
+    let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+    assert_eq!(bits(&resumed.nodes), bits(&straight.nodes));
+    assert_eq!(bits(&state.residuals), bits(&straight_residuals));
+    assert_eq!(resumed.fixed_mask(), straight.fixed_mask());
+}
+
+#[test]
+fn truncated_or_corrupt_checkpoints_fail_without_panicking() {
+    let path = temporary("corrupt.chk");
+    let state = SolverState { iteration: 3, over_relaxation: 1.5, residuals: vec![0.5, 0.25, 0.1] };
+    noisy_capacitor().save_checkpoint(&path, &state).unwrap();
+    let checkpoint = fs::read(&path).unwrap();
+
+    for length in 0..checkpoint.len() {
+        fs::write(&path, &checkpoint[..length]).unwrap();
+        assert!(Checkpoint::load(&path).is_err(), "truncated to {} bytes", length);
+    }
+    for i in 0..checkpoint.len() {
+        let mut corrupt = checkpoint.clone();
+        corrupt[i] ^= 0x10;
+        fs::write(&path, &corrupt).unwrap();
+        assert!(Checkpoint::load(&path).is_err(), "byte {} flipped", i);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn checkpoints_keep_the_open_border() {
+    let open = OpenBoundary { center: (10.0, 10.0), order: 2, inset: 3 };
+    let task = GridBuilder::new().size(20, 20).boundary(BoundaryCondition::Open(open))
+        .electrode(FixedBox { x: 8, y: 9, width: 2, height: 2, potential: 1.0 }).seed([7; 32]);
+    let mut grid = task.build().unwrap();
+    let path = temporary("open.chk");
+    grid.save_checkpoint(&path, &SolverState::new(1.8)).unwrap();
+    let Checkpoint { grid: mut resumed, .. } = Checkpoint::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(resumed.open_boundary(), Some(open));
+
+    let solve = grid.evaluate_open(1e-9, 1.8);
+    assert!(solve.0 > 1 && solve.1, "{:?}", solve);
+    assert_eq!(resumed.evaluate_open(1e-9, 1.8), solve);
+    assert_eq!(resumed.nodes, grid.nodes);
+
+    // evaluate solves the open border in the same passes, checkpointed solves refuse it
+    let mut watched = task.build().unwrap();
+    assert!(watched.evaluate(1e-9, 1.8, (10, 10), &mut Vec::new()));
+    assert_eq!(watched.nodes, grid.nodes);
+    let error = task.build().unwrap().evaluate_with_checkpoints(
+        1e-9, &mut SolverState::new(1.8), &path, 0, &AtomicBool::new(false)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert!(fs::metadata(&path).is_err());
+}
+
+// a square of charge in the middle of a 20 x 10 grid
+fn charge(x: f64, y: f64) -> f64 {
+    if (x - 10.0).abs() < 2.0 && (y - 5.0).abs() < 2.0 { 1.0 } else { 0.0 }
+}
+
+#[test]
+fn checkpoints_keep_the_source() {
+    let task = GridBuilder::new().size(20, 10).source(charge).seed([7; 32]);
+    let mut grid = task.build().unwrap();
+    let path = temporary("source.chk");
+    grid.save_checkpoint(&path, &SolverState::new(1.8)).unwrap();
+    let Checkpoint { grid: mut resumed, .. } = Checkpoint::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(resumed.source(), grid.source());
+    assert_eq!(grid.source().unwrap()[5 * 20 + 10], 1.0);
+
+    assert!(grid.evaluate(1e-10, 1.8, (10, 5), &mut Vec::new()));
+    assert!(resumed.evaluate(1e-10, 1.8, (10, 5), &mut Vec::new()));
+    assert_eq!(resumed.nodes, grid.nodes);
+    // the charge lifts the potential above the grounded border
+    assert!(grid.nodes[5 * 20 + 10] > 0.5, "{}", grid.nodes[5 * 20 + 10]);
+}
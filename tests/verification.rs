@@ -0,0 +1,610 @@
+//! Verification of the solver against closed form solutions of the Laplace and the Poisson
+//! equation.
+//!
+//! Most tasks use one node per unit and fix single nodes with 1x1 boxes, that way arbitrary
+//! Dirichlet boundary values can be prescribed. The manufactured solution of the Poisson equation
+//! gets its source from the builder.
+
+extern crate numeric_laplace;
+
+mod common;
+
+use numeric_laplace::*;
+use common::SCALE;
+use std::f64::consts::PI;
+
+/// boxes fixing every border node of a `width` x `height` grid to `potential(column, row)`
+fn border_boxes<F: Fn(usize, usize) -> f64>(width: usize, height: usize, potential: F)
+    -> Vec<FixedBox> {
+    let mut boxes = Vec::new();
+    for row in 0..height {
+        for column in 0..width {
+            if column == 0 || row == 0 || column == width - 1 || row == height - 1 {
+                boxes.push(node_box(column, row, potential(column, row)));
+            }
+        }
+    }
+    boxes
+}
+
+fn node_box(column: usize, row: usize, potential: f64) -> FixedBox {
+    FixedBox { x: column as isize, y: row as isize, width: 1, height: 1, potential }
+}
+
+/// evaluates the grid with the optimal over relaxation for a square of `n` nodes until the max
+/// delta is below `accepted_delta`, it has to converge
+fn solve(grid: &mut Grid, n: usize, accepted_delta: f64) {
+    converge(grid, 2.0 / (1.0 + (PI / n as f64).sin()), accepted_delta);
+}
+
+/// evaluates the grid with `over_relaxation`, watching the first node, it has to converge
+fn converge(grid: &mut Grid, over_relaxation: f64, accepted_delta: f64) {
+    let (x, y) = grid.scale.to_external(0.0, 0.0);
+    assert!(grid.evaluate(accepted_delta, over_relaxation, (x as isize, y as isize),
+                          &mut Vec::new()), "the solve did not converge");
+}
+
+/// biggest difference between the grid and `exact(column, row)` over the nodes for which
+/// `include(column, row)` is true
+fn max_error<E, I>(grid: &Grid, exact: E, include: I) -> f64
+    where E: Fn(usize, usize) -> f64, I: Fn(usize, usize) -> bool {
+    let mut error: f64 = 0.0;
+    for (i, &value) in grid.nodes.iter().enumerate() {
+        let (column, row) = (i % grid.width, i / grid.width);
+        if include(column, row) {
+            error = error.max((value - exact(column, row)).abs());
+        }
+    }
+    error
+}
+
+#[test]
+fn parallel_plates_give_a_linear_potential() {
+    let n = 41;
+    let exact = |column: usize, _row: usize| 100.0 * column as f64 / (n - 1) as f64;
+    let boxes = border_boxes(n, n, exact);
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    let mut watch_data = Vec::new();
+    assert!(grid.evaluate(1e-10, 1.85, (20, 20), &mut watch_data));
+
+    // the 5-point stencil is exact for linear functions, only the iteration error is left
+    assert!(max_error(&grid, exact, |_, _| true) < 1e-6);
+}
+
+#[test]
+fn parallel_plates_multi_threaded() {
+    let n = 81;
+    let exact = |column: usize, _row: usize| 100.0 * column as f64 / (n - 1) as f64;
+    let boxes = border_boxes(n, n, exact);
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    assert!(grid.evaluate_multi_thread(1e-10, 1.9, 4));
+
+    assert!(max_error(&grid, exact, |_, _| true) < 1e-6);
+}
+
+/// potential between two coaxial cylinders with radii `a` (at 1 V) and `b` (at 0 V)
+#[test]
+fn coaxial_cylinders_give_a_logarithmic_potential() {
+    let n = 161;
+    let center = (n / 2) as f64;
+    let (a, b) = (10.0, 70.0);
+    let radius = |column: usize, row: usize|
+        ((column as f64 - center).powi(2) + (row as f64 - center).powi(2)).sqrt();
+
+    let mut boxes = Vec::new();
+    for row in 0..n {
+        for column in 0..n {
+            let r = radius(column, row);
+            if r <= a {
+                boxes.push(node_box(column, row, 1.0));
+            } else if r >= b {
+                boxes.push(node_box(column, row, 0.0));
+            }
+        }
+    }
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    solve(&mut grid, n, 1e-9);
+
+    let exact = |column: usize, row: usize| (b / radius(column, row)).ln() / (b / a).ln();
+    let annulus = |column: usize, row: usize| {
+        let r = radius(column, row);
+        r > a && r < b
+    };
+    // the staircase approximation of the cylinders limits the accuracy
+    let error = max_error(&grid, exact, annulus);
+    assert!(error < 0.03, "max error {}", error);
+
+    // the staircase does not converge steadily, with the inner cylinder as a conductor and the
+    // outer one on the border the logarithmic potential converges with second order
+    let (coarse, fine) = (conductor_errors(2).0, conductor_errors(4).0);
+    let order = (coarse / fine).log2();
+    assert!(order > 1.8, "observed order {} ({} and {})", order, coarse, fine);
+}
+
+/// square with the top side at 1 V and the other sides at 0 V, solved with a Fourier series
+fn rectangle_exact(n: usize, column: usize, row: usize) -> f64 {
+    let side = (n - 1) as f64;
+    let (x, y) = (column as f64 / side, (side - row as f64) / side);
+    let mut sum = 0.0;
+    for k in 0..2000 {
+        let m = (2 * k + 1) as f64 * PI;
+        // sinh(m y) / sinh(m) without overflowing
+        let ratio = (m * (y - 1.0)).exp() * (1.0 - (-2.0 * m * y).exp())
+            / (1.0 - (-2.0 * m).exp());
+        sum += 4.0 / m * (m * x).sin() * ratio;
+    }
+    sum
+}
+
+/// error at a quarter of the width and height of the square with the top side at 1 V (the
+/// center would be exact by symmetry), `n - 1` has to be a multiple of 4
+fn rectangle_probe_error(n: usize) -> f64 {
+    let boxes = border_boxes(n, n, |column, row|
+        if row == 0 && column > 0 && column < n - 1 { 1.0 } else { 0.0 });
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    solve(&mut grid, n, 1e-12);
+    let quarter = (n - 1) / 4;
+    (grid.nodes[quarter * n + quarter] - rectangle_exact(n, quarter, quarter)).abs()
+}
+
+#[test]
+fn rectangle_matches_fourier_series() {
+    let n = 41;
+    let boxes = border_boxes(n, n, |column, row|
+        if row == 0 && column > 0 && column < n - 1 { 1.0 } else { 0.0 });
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    solve(&mut grid, n, 1e-10);
+
+    // the corners are discontinuous, so they are left out
+    let away_from_top = |_column: usize, row: usize| row >= n / 4;
+    let error = max_error(&grid, |column, row| rectangle_exact(n, column, row), away_from_top);
+    assert!(error < 2e-3, "max error {}", error);
+}
+
+#[test]
+fn rectangle_converges_with_second_order() {
+    let coarse = rectangle_probe_error(21);
+    let fine = rectangle_probe_error(41);
+    let order = (coarse / fine).log2();
+    assert!(order > 1.8, "observed order {}", order);
+}
+
+/// manufactured harmonic solution u = e^(pi x) sin(pi y) on the unit square
+fn manufactured_error(n: usize) -> f64 {
+    let h = 1.0 / (n - 1) as f64;
+    let exact = |column: usize, row: usize|
+        (PI * column as f64 * h).exp() * (PI * row as f64 * h).sin();
+    let boxes = border_boxes(n, n, exact);
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    solve(&mut grid, n, 1e-12);
+    max_error(&grid, exact, |_, _| true)
+}
+
+#[test]
+fn manufactured_solution_converges_with_second_order() {
+    let errors: Vec<f64> = [11, 21, 41].iter().map(|&n| manufactured_error(n)).collect();
+    // the solution goes up to e^pi (about 23)
+    assert!(errors[2] < 5e-3, "max error {}", errors[2]);
+    for pair in errors.windows(2) {
+        let order = (pair[0] / pair[1]).log2();
+        assert!(order > 1.8, "observed order {} (errors {:?})", order, errors);
+    }
+}
+
+// manufactured solution of the Poisson equation -∇²u = f with the source f = 2 sin x sin y
+fn poisson_exact(x: f64, y: f64) -> f64 { x.sin() * y.sin() }
+
+fn poisson_source(x: f64, y: f64) -> f64 { 2.0 * x.sin() * y.sin() }
+
+/// max error of `poisson_exact` on a 3 x 3 square with `nodes_per_unit`, solved on `threads`
+/// threads (0 for `sweep`)
+fn poisson_error(nodes_per_unit: usize, threads: usize) -> f64 {
+    let mut grid = GridBuilder::new().size(3, 3).resolution(nodes_per_unit)
+        .boundary(BoundaryCondition::Function(poisson_exact)).source(poisson_source)
+        .seed([7; 32]).build().unwrap();
+    if threads > 0 {
+        let over_relaxation = 2.0 / (1.0 + (PI / grid.width as f64).sin());
+        assert!(grid.evaluate_multi_thread(1e-13, over_relaxation, threads));
+    } else {
+        solve(&mut grid, 3 * nodes_per_unit, 1e-13);
+    }
+    max_error(&grid, |column, row| {
+        let (x, y) = grid.scale.to_external(column as f64, row as f64);
+        poisson_exact(x, y)
+    }, |_, _| true)
+}
+
+#[test]
+fn poisson_manufactured_solution_converges_with_second_order() {
+    let errors: Vec<f64> = [4, 8, 16].iter().map(|&n| poisson_error(n, 0)).collect();
+    assert!(errors[2] < 1e-3, "max error {}", errors[2]);
+    for pair in errors.windows(2) {
+        let order = (pair[0] / pair[1]).log2();
+        assert!(order > 1.8, "observed order {} (errors {:?})", order, errors);
+    }
+    let threaded = poisson_error(16, 2);
+    assert!((threaded - errors[2]).abs() < 1e-9, "{} vs {}", threaded, errors[2]);
+
+    // without the source the same border gives a harmonic potential far from the solution
+    let mut laplace = GridBuilder::new().size(3, 3).resolution(8)
+        .boundary(BoundaryCondition::Function(poisson_exact)).seed([7; 32]).build().unwrap();
+    solve(&mut laplace, 24, 1e-13);
+    let center = laplace.sample(1.5, 1.5, Interpolation::Bilinear).unwrap();
+    assert!((center - poisson_exact(1.5, 1.5)).abs() > 0.3, "{}", center);
+    assert!(laplace.source().is_none());
+}
+
+/// `manufactured_error` with the 9-point stencil, evaluated on two threads if `threaded`
+fn nine_point_error(n: usize, threaded: bool) -> f64 {
+    let h = 1.0 / (n - 1) as f64;
+    let exact = |column: usize, row: usize|
+        (PI * column as f64 * h).exp() * (PI * row as f64 * h).sin();
+    let mut grid = GridBuilder::new().size(n, n).electrodes(&border_boxes(n, n, exact))
+        .stencil(StencilKind::NinePoint).seed([7; 32]).build().unwrap();
+    if threaded {
+        assert!(grid.evaluate_multi_thread(1e-13, 2.0 / (1.0 + (PI / n as f64).sin()), 2));
+    } else {
+        solve(&mut grid, n, 1e-13);
+    }
+    max_error(&grid, exact, |_, _| true)
+}
+
+#[test]
+fn nine_point_stencil_converges_with_fourth_order() {
+    let errors = [nine_point_error(11, false), nine_point_error(21, false)];
+    let order = (errors[0] / errors[1]).log2();
+    assert!(order > 3.8, "observed order {} (errors {:?})", order, errors);
+    assert!(errors[1] < manufactured_error(21) / 100.0);
+    let threaded = nine_point_error(21, true);
+    assert!((threaded - errors[1]).abs() < 1e-9, "{} vs {}", threaded, errors[1]);
+
+    let stretched = GridBuilder::new().size(10, 10).spacing(1.0, 0.4)
+        .stencil(StencilKind::NinePoint).build();
+    assert!(stretched.is_err());
+}
+
+#[test]
+fn sampling_reproduces_linear_potential_between_nodes() {
+    let n = 21;
+    let exact = |column: usize, row: usize| 2.0 * column as f64 + 3.0 * row as f64;
+    let boxes = border_boxes(n, n, exact);
+    let mut grid = Grid::new(n, n, &boxes, &SCALE, Some([7; 32])).unwrap();
+    solve(&mut grid, n, 1e-12);
+
+    for &interpolation in [Interpolation::Bilinear, Interpolation::Bicubic].iter() {
+        let value = grid.sample(7.25, 11.5, interpolation).unwrap();
+        assert!((value - (2.0 * 7.25 + 3.0 * 11.5)).abs() < 1e-8);
+        let field = grid.sample_field(7.25, 11.5, interpolation).unwrap();
+        assert!((field.0 + 2.0).abs() < 1e-8 && (field.1 + 3.0).abs() < 1e-8);
+    }
+    assert!(grid.sample(-1.0, 3.0, Interpolation::Bilinear).is_err());
+}
+
+fn saddle(x: f64, y: f64) -> f64 { x * y }
+
+#[test]
+fn cascade_solves_every_level_to_the_exact_saddle() {
+    // a spacing of 1/4 unit is solved with every 4th, every 2nd and then every node, x * y is
+    // exact on all of them so only the iteration error of the last level is left
+    let task = GridBuilder::new().size(10, 8).resolution(4).origin(-3.0, -2.0)
+        .boundary(BoundaryCondition::Function(saddle)).seed([7; 32]);
+    let grid = Grid::solve_cascade(&task, 1e-12, 1.8).unwrap();
+    assert_eq!((grid.width, grid.height()), (40, 32));
+
+    let exact = |column: usize, row: usize| {
+        let (x, y) = grid.scale.to_external(column as f64, row as f64);
+        saddle(x, y)
+    };
+    assert!(max_error(&grid, exact, |_, _| true) < 1e-8);
+
+    let outside = FixedBox { x: 9, y: 0, width: 2, height: 1, potential: 1.0 };
+    assert!(matches!(Grid::solve_cascade(&task.clone().electrode(outside), 1e-12, 1.8),
+                     Err(GridError::OutOfBounds { index: 0, .. })));
+    // an over relaxation above 2 diverges as soon as a level does not start at the solution
+    assert!(matches!(Grid::solve_cascade(&task, 1e-12, 2.5),
+                     Err(GridError::NotConverged { .. })));
+}
+
+// a line charge just outside the corner at the origin, the potential is steep close to it
+fn line_charge(x: f64, y: f64) -> f64 { 10.0 * ((x + 0.5).powi(2) + (y + 0.5).powi(2)).ln() }
+
+/// max error of the line charge on a grid graded towards it, with spacings between `min_spacing`
+/// and five times that
+fn graded_line_charge_error(min_spacing: f64, ratio: f64) -> f64 {
+    let grading = Grading::new(&[0.0], min_spacing, 5.0 * min_spacing, ratio);
+    let mut grid = GridBuilder::new().size(16, 10).origin(0.0, 10.0).invert(false, true)
+        .grade(Some(grading.clone()), Some(grading))
+        .boundary(BoundaryCondition::Function(line_charge)).seed([7; 32]).build().unwrap();
+    assert!(grid.scale.x.is_graded() && grid.scale.y.is_graded());
+    converge(&mut grid, 1.9, 1e-12);
+
+    let exact = |column: usize, row: usize| {
+        let (x, y) = grid.scale.to_external(column as f64, row as f64);
+        line_charge(x, y)
+    };
+    max_error(&grid, exact, |_, _| true)
+}
+
+#[test]
+fn graded_mesh_converges_with_second_order() {
+    // halving the spacings and taking the square root of the ratio refines every cell of the
+    // grading by two, the stencil with unequal spacings still converges with second order
+    let coarse = graded_line_charge_error(0.2, 1.3);
+    let medium = graded_line_charge_error(0.1, 1.3f64.sqrt());
+    let fine = graded_line_charge_error(0.05, 1.3f64.sqrt().sqrt());
+    assert!(coarse < 0.03, "{}", coarse);
+    for &(error, finer_error) in [(coarse, medium), (medium, fine)].iter() {
+        let order = (error / finer_error).log2();
+        assert!(order > 1.8 && order < 2.3, "{} {} {}", coarse, medium, fine);
+    }
+}
+
+#[test]
+fn adaptive_refinement_beats_the_coarse_grid_with_fewer_cells() {
+    let task = GridBuilder::new().size(17, 11).boundary(BoundaryCondition::Function(line_charge))
+        .seed([7; 32]);
+    let mut coarse = task.build().unwrap();
+    solve(&mut coarse, 11, 1e-10);
+    let mut refinement = Refinement::new(0.05);
+    refinement.max_level = 3;
+    let mesh = Grid::solve_adaptive(&task, &refinement, 1e-10, 1.8).unwrap();
+
+    let points: Vec<(f64, f64)> = (0..16)
+        .flat_map(|i| (0..10).map(move |j| (i as f64 + 0.3, j as f64 + 0.6))).collect();
+    let error = |sample: &dyn Fn(f64, f64) -> f64| points.iter()
+        .fold(0.0f64, |max, &(x, y)| max.max((sample(x, y) - line_charge(x, y)).abs()));
+    let coarse_error = error(&|x, y| coarse.sample(x, y, Interpolation::Bilinear).unwrap());
+    let adaptive_error = error(&|x, y| mesh.sample(x, y).unwrap());
+
+    assert_eq!(mesh.max_level(), 3);
+    // a uniform grid with the finest cells everywhere has 16 * 10 * 64 of them
+    assert!(mesh.leaf_count() < 16 * 10 * 64 / 2);
+    assert!(adaptive_error < coarse_error / 3.0, "{} vs {}", adaptive_error, coarse_error);
+    let resampled = mesh.to_grid(1);
+    assert_eq!((resampled.width, resampled.height()), (33, 21));
+    assert!((resampled.sample(7.5, 7.0, Interpolation::Bilinear).unwrap()
+        - mesh.sample(7.5, 7.0).unwrap()).abs() < 0.1);
+
+    assert_eq!(Grid::solve_adaptive(&task, &refinement, 1e-10, 2.5).err(),
+               Some(GridError::NotConverged { spacing: (1.0, 1.0) }));
+    let axisymmetric = task.geometry(Geometry::Axisymmetric);
+    assert!(matches!(Grid::solve_adaptive(&axisymmetric, &refinement, 1e-10, 1.8),
+                     Err(GridError::Unsupported(_))));
+}
+
+// harmonic in cylindrical coordinates (but not in planar ones), the r-z stencil is exact for it
+fn axisymmetric_quadratic(r: f64, z: f64) -> f64 { z * z - r * r / 2.0 }
+
+#[test]
+fn axisymmetric_grid_reproduces_a_quadratic_potential_including_the_axis() {
+    let mut grid = GridBuilder::new().size(8, 10).resolution(2).geometry(Geometry::Axisymmetric)
+        .boundary(BoundaryCondition::Function(axisymmetric_quadratic)).seed([3; 32]).build()
+        .unwrap();
+    assert!(!grid.fixed_mask()[grid.width * 5]);
+    solve(&mut grid, 20, 1e-12);
+    let error = max_error(&grid, |column, row| {
+        let (r, z) = grid.scale.to_external(column as f64, row as f64);
+        axisymmetric_quadratic(r, z)
+    }, |_, _| true);
+    assert!(error < 1e-8, "max error {}", error);
+
+    let inverted = GridBuilder::new().size(8, 10).invert(true, false)
+        .geometry(Geometry::Axisymmetric).build();
+    assert!(inverted.is_err());
+}
+
+/// cube with the face at layer 0 at 1 V and the other faces at 0 V, solved with a Fourier series
+fn cube_exact(n: usize, column: usize, row: usize, layer: usize) -> f64 {
+    let side = (n - 1) as f64;
+    let (x, y, z) = (column as f64 / side, row as f64 / side, (side - layer as f64) / side);
+    let mut sum = 0.0;
+    // only used away from the face, where the terms decay quickly
+    for j in 0..40 {
+        for k in 0..40 {
+            let (m, p) = ((2 * j + 1) as f64 * PI, (2 * k + 1) as f64 * PI);
+            let q = (m * m + p * p).sqrt();
+            // sinh(q z) / sinh(q) without overflowing
+            let ratio = (q * (z - 1.0)).exp() * (1.0 - (-2.0 * q * z).exp())
+                / (1.0 - (-2.0 * q).exp());
+            sum += 16.0 / (m * p) * (m * x).sin() * (p * y).sin() * ratio;
+        }
+    }
+    sum
+}
+
+#[test]
+fn cube_matches_fourier_series_on_multiple_threads() {
+    let n = 21;
+    let faces = [FixedBox3 { x: 1, y: 1, z: 0, width: n - 2, height: n - 2, depth: 1,
+        potential: 1.0 }];
+    let mut grid = Grid3::new(n, n, n, &faces, &Scale3::uniform(1.0), Some([7; 32])).unwrap();
+    let mut threaded = Grid3Builder::new().size(n, n, n).electrodes(&faces).seed([7; 32]).build()
+        .unwrap();
+    assert_eq!(threaded.nodes, grid.nodes);
+    assert_eq!((grid.width, grid.height, grid.depth()), (n, n, n));
+    assert!(grid.evaluate(1e-11, 1.75));
+    assert!(threaded.evaluate_multi_thread(1e-11, 1.75, 2));
+
+    // the edges of the face are discontinuous, so the layers next to it are left out
+    let mut error: f64 = 0.0;
+    for (i, (&value, &other)) in grid.nodes.iter().zip(threaded.nodes.iter()).enumerate() {
+        let (column, row, layer) = (i % n, i / n % n, i / (n * n));
+        assert!((value - other).abs() < 1e-8);
+        if layer >= n / 4 && column % 2 == 0 && row % 2 == 0 {
+            error = error.max((value - cube_exact(n, column, row, layer)).abs());
+        }
+    }
+    assert!(error < 5e-3, "max error {}", error);
+
+    let layer = grid.slice(Plane::Xy, 10.0).unwrap();
+    assert_eq!(&layer.nodes[..], &grid.nodes[10 * n * n..11 * n * n]);
+    let (xz, yz) = (grid.slice(Plane::Xz, 10.0).unwrap(), grid.slice(Plane::Yz, 10.0).unwrap());
+    assert!(xz.nodes.iter().zip(yz.nodes.iter()).all(|(a, b)| (a - b).abs() < 1e-8));
+    assert!(grid.slice(Plane::Xy, 30.0).is_none());
+}
+
+const INNER_RADIUS: f64 = 3.3;
+const OUTER_RADIUS: f64 = 40.0;
+
+/// potential around a cylinder with radius `INNER_RADIUS` at 1 V centered at (16, 16) inside of
+/// a grounded one with radius `OUTER_RADIUS`
+fn coaxial(x: f64, y: f64) -> f64 {
+    let r = ((x - 16.0).powi(2) + (y - 16.0).powi(2)).sqrt();
+    (OUTER_RADIUS / r).ln() / (OUTER_RADIUS / INNER_RADIUS).ln()
+}
+
+/// max error over the dynamic nodes
+fn coaxial_error(grid: &Grid) -> f64 {
+    let fixed = grid.fixed_mask();
+    max_error(grid, |column, row| {
+        let (x, y) = grid.scale.to_external(column as f64, row as f64);
+        coaxial(x, y)
+    }, |column, row| !fixed[row * grid.width + column])
+}
+
+/// errors of a cylinder as a conductor and of the same nodes fixed as a staircase
+fn conductor_errors(nodes_per_unit: usize) -> (f64, f64) {
+    let cylinder = Shape::Circle { x: 16.0, y: 16.0, radius: INNER_RADIUS };
+    let mut grid = GridBuilder::new().size(33, 33).resolution(nodes_per_unit)
+        .boundary(BoundaryCondition::Function(coaxial))
+        .conductor(Conductor { shape: cylinder, potential: 1.0 }).seed([7; 32]).build().unwrap();
+    let mut staircase = Grid::from_parts(grid.nodes.clone(), grid.width, &grid.fixed_mask(),
+                                         grid.scale.clone());
+    solve(&mut grid, 33 * nodes_per_unit, 1e-12);
+    solve(&mut staircase, 33 * nodes_per_unit, 1e-12);
+    (coaxial_error(&grid), coaxial_error(&staircase))
+}
+
+#[test]
+fn curved_conductor_converges_with_second_order_unlike_the_staircase() {
+    let (coarse, coarse_staircase) = conductor_errors(1);
+    let (fine, fine_staircase) = conductor_errors(2);
+    assert!(fine < fine_staircase / 20.0, "{} vs staircase {}", fine, fine_staircase);
+    assert!(coarse < coarse_staircase / 20.0, "{} vs staircase {}", coarse, coarse_staircase);
+    let order = (coarse / fine).log2();
+    assert!(order > 1.5, "observed order {} ({} and {})", order, coarse, fine);
+
+    let conflict = GridBuilder::new().size(20, 20)
+        .electrode(FixedBox { x: 5, y: 5, width: 2, height: 2, potential: 2.0 })
+        .conductor(Conductor { shape: Shape::Polygon(vec![(4.0, 4.0), (8.0, 5.0), (5.0, 9.0)]),
+            potential: 1.0 }).build();
+    assert!(conflict.is_err());
+}
+
+/// potential of a sphere with radius 1 at 1 V centered at z = 4 on the axis, 0 at infinity
+fn isolated_sphere(r: f64, z: f64) -> f64 {
+    1.0 / (r * r + (z - 4.0).powi(2)).sqrt()
+}
+
+/// max error of an isolated sphere in a tight r-z domain of 4 x 8 units with `boundary` and the
+/// passes of the converged solve
+fn isolated_sphere_error(boundary: BoundaryCondition) -> (f64, usize) {
+    let sphere = Shape::Circle { x: 0.0, y: 4.0, radius: 1.0 };
+    let mut grid = GridBuilder::new().size(4, 8).resolution(8).geometry(Geometry::Axisymmetric)
+        .boundary(boundary).conductor(Conductor { shape: sphere, potential: 1.0 })
+        .seed([3; 32]).build().unwrap();
+    let (passes, converged) = grid.evaluate_open(1e-10, 1.9);
+    assert!(converged);
+    let fixed = grid.fixed_mask();
+    (max_error(&grid, |column, row| {
+        let (r, z) = grid.scale.to_external(column as f64, row as f64);
+        isolated_sphere(r, z)
+    }, |column, row| !fixed[row * grid.width + column]), passes)
+}
+
+#[test]
+fn open_border_solves_an_isolated_sphere_on_a_tight_domain() {
+    let open = OpenBoundary { center: (0.0, 4.0), order: 4, inset: 8 };
+    let (grounded, fixed_passes) = isolated_sphere_error(BoundaryCondition::Potential(0.0));
+    let (error, passes) = isolated_sphere_error(BoundaryCondition::Open(open));
+    assert!(grounded > 0.2, "grounded border error {}", grounded);
+    assert!(error < 2e-3, "open border error {}", error);
+    assert_eq!(fixed_passes, 0);
+    assert!(passes > 1, "{} passes", passes);
+
+    let outside = GridBuilder::new().size(10, 10).boundary(BoundaryCondition::Open(open))
+        .electrode(FixedBox { x: 1, y: 4, width: 1, height: 1, potential: 1.0 }).build();
+    assert!(matches!(outside, Err(GridError::InvalidOpenBoundary(_))));
+}
+
+/// max error of the slowest mode of a grounded 32 x 32 plate after a time of 50 with
+/// `steps` steps of `stepping`, the temperature at the center goes to `probe_data`
+fn heat_mode_error(stepping: TimeStepping, steps: usize, probe_data: &mut Vec<f64>) -> f64 {
+    let mode = |column: usize, row: usize|
+        (PI * column as f64 / 31.0).sin() * (PI * row as f64 / 31.0).sin();
+    let mut grid = GridBuilder::new().size(32, 32).initial_guess(InitialGuess::Zero).build()
+        .unwrap();
+    for i in 0..grid.nodes.len() {
+        grid.nodes[i] = mode(i % 32, i / 32);
+    }
+    let transient = Transient { diffusivity: 1.0, time_step: 50.0 / steps as f64, steps,
+        stepping, snapshot_every: 0, snapshot_prefix: String::new(),
+        probes: vec![(15.5, 15.5)] };
+    grid.evolve(&transient, probe_data).unwrap();
+    let decay = (-2.0 * PI * PI / (31.0 * 31.0) * 50.0).exp();
+    max_error(&grid, |column, row| decay * mode(column, row), |_, _| true)
+}
+
+#[test]
+fn heat_equation_matches_the_decay_of_a_mode() {
+    let mut probe_data = Vec::new();
+    let explicit = heat_mode_error(TimeStepping::Explicit, 200, &mut probe_data);
+    assert!(explicit < 1e-3, "explicit error {}", explicit);
+    assert_eq!(probe_data.len(), 202);
+    assert_eq!(probe_data[201], f64::NEG_INFINITY);
+    let implicit = TimeStepping::CrankNicolson { accepted_delta: 1e-12, over_relaxation: 1.2 };
+    let crank_nicolson = heat_mode_error(implicit, 20, &mut Vec::new());
+    assert!(crank_nicolson < 1e-3, "Crank-Nicolson error {}", crank_nicolson);
+
+    let mut grid = GridBuilder::new().size(32, 32).build().unwrap();
+    assert_eq!(grid.stable_time_step(1.0), 0.25);
+    let unstable = Transient { diffusivity: 1.0, time_step: 0.3, steps: 1,
+        stepping: TimeStepping::Explicit, snapshot_every: 0, snapshot_prefix: String::new(),
+        probes: Vec::new() };
+    assert!(matches!(grid.evolve(&unstable, &mut Vec::new()),
+                     Err(TransientError::Unstable { .. })));
+    let diverging = Transient { time_step: 10.0,
+        stepping: TimeStepping::CrankNicolson { accepted_delta: 1e-9, over_relaxation: 2.5 },
+        ..unstable };
+    assert!(matches!(grid.evolve(&diverging, &mut Vec::new()),
+                     Err(TransientError::NotConverged { step: 1 })));
+}
+
+#[test]
+fn resistance_of_an_insulated_strip_matches_its_squares() {
+    let from = FixedBox { x: 0, y: 0, width: 1, height: 10, potential: 1.0 };
+    let to = FixedBox { x: 19, y: 0, width: 1, height: 10, potential: 0.0 };
+    let strip = |regions: Vec<ConductivityBox>| {
+        let conduction = Conduction { background: 1.0, regions, insulating_border: true };
+        let mut grid = GridBuilder::new().size(20, 10).electrode(from.clone())
+            .electrode(to.clone()).conduction(conduction).seed([5; 32]).build().unwrap();
+        solve(&mut grid, 20, 1e-13);
+        grid
+    };
+
+    // 19 units long between the terminals and 9 wide, half of it twice as conductive
+    let uniform = strip(Vec::new());
+    assert!((uniform.resistance(&from, &to).unwrap() - 19.0 / 9.0).abs() < 1e-9);
+    assert!((uniform.squares(&from, &to).unwrap() - 19.0 / 9.0).abs() < 1e-9);
+    // the potentials are read from the grid, not from the boxes
+    let relabeled = FixedBox { potential: 5.0, ..from.clone() };
+    assert_eq!(uniform.resistance(&relabeled, &to), uniform.resistance(&from, &to));
+    let series = strip(vec![ConductivityBox { x: 10, y: 0, width: 10, height: 10,
+                                              conductivity: 2.0 }]);
+    let expected = 10.0 / 9.0 + 9.0 / 18.0;
+    assert!((series.resistance(&from, &to).unwrap() - expected).abs() < 1e-9);
+    assert!(matches!(series.squares(&from, &to), Err(GridError::InvalidConduction(_))));
+
+    // a cutout only raises the resistance, the current still balances
+    let cutout = strip(vec![ConductivityBox { x: 5, y: 3, width: 6, height: 7,
+                                              conductivity: 0.0 }]);
+    let (out, back) = (cutout.terminal_current(&from).unwrap(),
+                       cutout.terminal_current(&to).unwrap());
+    assert!((out + back).abs() < 1e-9 && 1.0 / out > 19.0 / 9.0, "{} and {}", out, back);
+    assert!((cutout.squares(&from, &to).unwrap() - 1.0 / out).abs() < 1e-6);
+
+    let axisymmetric = GridBuilder::new().size(20, 10).geometry(Geometry::Axisymmetric)
+        .conduction(Conduction::default()).build();
+    assert!(matches!(axisymmetric, Err(GridError::InvalidConduction(_))));
+}
+